@@ -0,0 +1,198 @@
+use super::*;
+
+use std::fmt;
+
+/// `AP_IDR` - IDR register of any AccessPort, bank 0xF
+pub const AP_IDR: u8 = 0xFC;
+/// `AP_SCAN_MAX` - AP indexes available on ADIv5 DP, scan stops earlier at first empty IDR
+pub const AP_SCAN_MAX: u16 = 256;
+
+/// AP IDR designer (JEP106) codes
+pub const JEP106_FREESCALE: u16 = 0x00E;
+/// AP IDR `CLASS` of MEM-AP
+pub const AP_IDR_CLASS_MEM_AP: u8 = 0x8;
+/// AP IDR `TYPE` of AHB3 / AHB5 MEM-AP
+pub const AP_IDR_TYPE_AHB3: u8 = 0x1;
+pub const AP_IDR_TYPE_AHB5: u8 = 0x8;
+
+/// `ApKind` - AccessPort classified by IDR
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ApKind {
+    /// AHB MEM-AP - system memory and core debug registers
+    AhbMemAp,
+    /// MEM-AP on another bus (APB, AXI)
+    MemAp,
+    /// Kinetis MDM-AP
+    KinetisMdmAp,
+    Unknown,
+}
+
+impl ApKind {
+    pub fn from_idr(idr: u32) -> Self {
+        let designer = ((idr >> 17) & 0x7FF) as u16;
+        let class = ((idr >> 13) & 0xF) as u8;
+        let ap_type = (idr & 0xF) as u8;
+        match (designer, class, ap_type) {
+            (JEP106_FREESCALE, 0, _) => ApKind::KinetisMdmAp,
+            (_, AP_IDR_CLASS_MEM_AP, AP_IDR_TYPE_AHB3 | AP_IDR_TYPE_AHB5) => ApKind::AhbMemAp,
+            (_, AP_IDR_CLASS_MEM_AP, _) => ApKind::MemAp,
+            _ => ApKind::Unknown,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApKind::AhbMemAp => "AHB MEM-AP",
+            ApKind::MemAp => "MEM-AP",
+            ApKind::KinetisMdmAp => "Kinetis MDM-AP",
+            ApKind::Unknown => "unknown AP",
+        }
+    }
+}
+
+/// `ApInfo` - one AccessPort found by `scan_aps`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ApInfo {
+    pub index: u8,
+    pub idr: u32,
+    pub kind: ApKind,
+}
+
+impl fmt::Display for ApInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AP{} {:#010X} {}", self.index, self.idr, self.kind.name())
+    }
+}
+
+/// `ApPorts` - AccessPort indexes the sequence works with
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ApPorts {
+    pub mem_ap: u8,
+    pub mdm_ap: u8,
+    /// IDR of `mdm_ap`, selects the `DeviceFamily` candidates
+    pub mdm_ap_idr: u32,
+}
+
+impl Default for ApPorts {
+    fn default() -> Self {
+        Self {
+            mem_ap: MKE_DEFAULT_MEM_AP,
+            mdm_ap: MKE_MDM_AP_PORT,
+            mdm_ap_idr: IDR_REG_CHECK_VALUE,
+        }
+    }
+}
+
+/// `ApScan` - all AccessPorts which answered with non-zero IDR
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ApScan {
+    pub aps: Vec<ApInfo>,
+}
+
+impl ApScan {
+    /// `ports` - first AHB MEM-AP and the only Kinetis MDM-AP,
+    /// `Error::UnexpectedApTopology` if the part does not look like a Kinetis
+    pub fn ports(&self) -> Result<ApPorts, Error> {
+        let mut mdm_aps = self.aps.iter().filter(|ap| ap.kind == ApKind::KinetisMdmAp);
+        let mem_ap = self.aps.iter().find(|ap| ap.kind == ApKind::AhbMemAp);
+
+        let reason = match (mem_ap, mdm_aps.next(), mdm_aps.next()) {
+            (Some(mem_ap), Some(mdm_ap), None) => {
+                return Ok(ApPorts {
+                    mem_ap: mem_ap.index,
+                    mdm_ap: mdm_ap.index,
+                    mdm_ap_idr: mdm_ap.idr,
+                })
+            }
+            _ if self.aps.is_empty() => "no AP answered, check wiring and target power",
+            (_, None, _) => "no Kinetis MDM-AP, not a Kinetis part?",
+            (_, Some(_), Some(_)) => "more than one Kinetis MDM-AP",
+            (None, _, _) => "no AHB MEM-AP for core and system memory",
+        };
+        Err(Error::UnexpectedApTopology {
+            reason,
+            aps: self.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ApScan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.aps.is_empty() {
+            return write!(f, "no APs");
+        }
+        let aps: Vec<String> = self.aps.iter().map(|ap| ap.to_string()).collect();
+        write!(f, "{}", aps.join(", "))
+    }
+}
+
+/// `scan_aps` - read IDR of AP 0, 1, ... until the first empty one (APs are numbered contiguously)
+///
+/// An unimplemented AP may fault instead of reading 0, a read error after AP 0 ends the scan
+/// with the sticky errors cleared. Only a failed AP 0 read is returned as error.
+pub fn scan_aps<T: Transport + ?Sized>(iface: &mut T) -> Result<ApScan, Error> {
+    let mut scan = ApScan::default();
+    for index in 0..AP_SCAN_MAX {
+        let index = index as u8;
+        let idr = match iface.read_ap_register(index, AP_IDR) {
+            Ok(idr) => idr,
+            Err(err) if index > 0 => {
+                log::debug!("AP {} IDR read failed, scan ended: {}", index, report(&err));
+                clear_sticky_errors(iface)?;
+                break;
+            }
+            Err(err) => return Err(err),
+        };
+        if idr == 0 {
+            break;
+        }
+        scan.aps.push(ApInfo {
+            index,
+            idr,
+            kind: ApKind::from_idr(idr),
+        });
+    }
+    log::info!("AP scan: {}", scan);
+    Ok(scan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_of_kinetis_aps() {
+        assert_eq!(ApKind::from_idr(SIM_AHB_AP_IDR), ApKind::AhbMemAp);
+        assert_eq!(ApKind::from_idr(IDR_REG_CHECK_VALUE), ApKind::KinetisMdmAp);
+        assert_eq!(ApKind::from_idr(MDM_IDR_CM4), ApKind::KinetisMdmAp);
+    }
+
+    #[test]
+    fn kind_by_mem_ap_bus_type() {
+        /* AHB5 MEM-AP of v8-M parts is still the system bus */
+        assert_eq!(ApKind::from_idr(0x0477_0008), ApKind::AhbMemAp);
+        assert_eq!(ApKind::from_idr(0x0477_0002), ApKind::MemAp);
+        assert_eq!(ApKind::from_idr(0x0476_0010), ApKind::Unknown);
+    }
+
+    #[test]
+    fn scan_finds_sim_ports() {
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        let scan = scan_aps(&mut sim).unwrap();
+        assert_eq!(scan.aps.len(), 2, "{}", scan);
+        assert_eq!(scan.ports().unwrap(), ApPorts::default());
+    }
+
+    #[test]
+    fn ports_without_mdm_ap() {
+        let scan = ApScan { aps: vec![ApInfo { index: 0, idr: SIM_AHB_AP_IDR, kind: ApKind::AhbMemAp }] };
+        match scan.ports() {
+            Err(Error::UnexpectedApTopology { reason, aps }) => {
+                assert!(reason.starts_with("no Kinetis MDM-AP"), "{}", reason);
+                assert_eq!(aps, "AP0 0x04770031 AHB MEM-AP");
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(ApScan::default().ports(), Err(Error::UnexpectedApTopology { .. })));
+    }
+}
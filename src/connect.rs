@@ -0,0 +1,629 @@
+use super::*;
+
+use std::fmt;
+
+/// ARMv6-M `AIRCR` - Application Interrupt and Reset Control Register
+pub const AIRCR_ADDRESS: u64 = 0xE000_ED0C;
+/// `AIRCR` write key and `SYSRESETREQ`
+pub const AIRCR_VECTKEY: u32 = 0x05FA_0000;
+pub const AIRCR_SYSRESETREQ: u32 = 1 << 2;
+
+/// `RECOVERY_ATTEMPTS` - default attempt budget of `ConnectStrategy::WakeAndCatch`
+pub const RECOVERY_ATTEMPTS: u32 = 20;
+/// `RECOVERY_RETRY_DELAY_MS` - pause between wake and catch attempts
+pub const RECOVERY_RETRY_DELAY_MS: u64 = 5;
+
+/// `ConnectStrategy` - how the target is reset (or not) while the debugger takes the core
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ConnectStrategy {
+    /// AN4835 steps: MDM-AP System Reset Request held, Debug Request, DHCSR halt, release reset
+    #[default]
+    An4835MdmReset,
+    /// probe NRST line held low during attach and halt, for firmware disabling SWD pins early
+    HardwareReset,
+    /// core halted, then reset by AIRCR SYSRESETREQ, for boards with NRST used as GPIO
+    SoftwareReset,
+    /// no reset at all, halt the running firmware where it is
+    HotAttach,
+    /// AN4835 with MDM-AP Core Hold Reset: system leaves reset with the core held,
+    /// core is halted by DHCSR before the hold is released, for firmware disabling SWD pins early
+    CoreHoldRelease,
+    /// recovery of firmware entering STOP / VLLS right after boot: reset hold, debug request
+    /// and halt are repeated until the core is caught before it enters low power
+    WakeAndCatch,
+}
+
+impl ConnectStrategy {
+    pub const ALL: [ConnectStrategy; 6] = [
+        ConnectStrategy::An4835MdmReset,
+        ConnectStrategy::HardwareReset,
+        ConnectStrategy::SoftwareReset,
+        ConnectStrategy::HotAttach,
+        ConnectStrategy::CoreHoldRelease,
+        ConnectStrategy::WakeAndCatch,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConnectStrategy::An4835MdmReset => "an4835",
+            ConnectStrategy::HardwareReset => "hardware-reset",
+            ConnectStrategy::SoftwareReset => "software-reset",
+            ConnectStrategy::HotAttach => "hot-attach",
+            ConnectStrategy::CoreHoldRelease => "core-hold",
+            ConnectStrategy::WakeAndCatch => "wake-and-catch",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|strategy| strategy.name() == name)
+    }
+}
+
+/// `ConnectOptions` - options of `connect`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectOptions {
+    pub strategy: ConnectStrategy,
+    /// mass erase the target if it is secured
+    pub mass_erase: bool,
+    /// arm `DEMCR` `VC_CORERESET` before reset release and check the core halted at the reset vector
+    pub vector_catch: bool,
+    /// attempt budget of `ConnectStrategy::WakeAndCatch`
+    pub recovery_attempts: u32,
+    pub recovery_retry_delay: time::Duration,
+    /// fail if `DeviceInfo::part_number` of the halted target differs, e.g. `KE14Z256`
+    pub expected_part: Option<String>,
+    /// `DeviceFamily` name, e.g. `KE1xZ`, `None` - detected by `detect_device_family`
+    pub device: Option<String>,
+    /// allowed target voltage checked before attach, `None` - no check
+    pub target_voltage: Option<VoltageRange>,
+    /// `target_voltage` is the `DeviceFamily::supply` default, checked again by the detected family
+    pub voltage_from_family: bool,
+    /// MDM-AP wait deadlines, applied to every `MdmAP` of the strategies
+    pub timeouts: MdmTimeouts,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            strategy: ConnectStrategy::default(),
+            mass_erase: false,
+            vector_catch: false,
+            recovery_attempts: RECOVERY_ATTEMPTS,
+            recovery_retry_delay: time::Duration::from_millis(RECOVERY_RETRY_DELAY_MS),
+            expected_part: None,
+            device: None,
+            target_voltage: Some(any_family_supply()),
+            voltage_from_family: true,
+            timeouts: MdmTimeouts::default(),
+        }
+    }
+}
+
+/// `Connection` - result of `connect`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Connection {
+    pub ports: ApPorts,
+    pub family: &'static DeviceFamily,
+    pub core_state: CoreState,
+    /// PC / SP of the halt at reset vector, with `options.vector_catch`
+    pub reset_halt: Option<ResetHalt>,
+    pub device: DeviceInfo,
+}
+
+impl fmt::Display for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", self.device, self.core_state)?;
+        if let Some(halt) = &self.reset_halt {
+            write!(f, ", reset halt PC {:#010X} SP {:#010X}", halt.pc, halt.sp)?;
+        }
+        Ok(())
+    }
+}
+
+/// `connect` - take the core into debug halt with the selected `ConnectStrategy`
+///
+/// MDM-AP and AHB MEM-AP are found by `scan_aps` first, the halted part is identified by
+/// `options.device` or `detect_device_family` and `read_device_info`.
+/// `Error::UnknownDevice` if no `DeviceFamily` has the MDM-AP IDR and none is named,
+/// `Error::IdrMismatch` if the named family has another MDM-AP IDR,
+/// `Error::DhcsrHaltFailed` if the core is not halted at the end,
+/// `Error::PartMismatch` if it is not `options.expected_part`.
+pub fn connect<T: Transport + ?Sized>(iface: &mut T, options: &ConnectOptions) -> Result<Connection, Error> {
+
+    let ports = scan_aps(iface)?.ports()?;
+    let named = match &options.device {
+        Some(name) => Some(find_device_family(name)?),
+        None => None,
+    };
+    /* MDM-AP IDR of the named family, or of the families detected later */
+    let idr_family = match named {
+        Some(family) if family.mdm_ap_idr != ports.mdm_ap_idr => {
+            return Err(Error::IdrMismatch { expected: family.mdm_ap_idr, found: ports.mdm_ap_idr })
+        }
+        Some(family) => family,
+        None => match families_for_idr(ports.mdm_ap_idr).first() {
+            Some(family) => *family,
+            None => {
+                return Err(Error::UnknownDevice {
+                    what: format!("MDM-AP IDR {:#010X}", ports.mdm_ap_idr),
+                    known: known_device_families(),
+                })
+            }
+        },
+    };
+    log::info!("connect strategy : {}, MDM-AP {}, MEM-AP {}", options.strategy.name(), ports.mdm_ap, ports.mem_ap);
+    let (core_state, reset_halt) = connect_strategy(iface, options, idr_family, &ports)?;
+
+    let family = match named {
+        Some(family) => family,
+        None => detect_device_family(iface, ports.mem_ap, ports.mdm_ap_idr)?,
+    };
+    check_status_bits(iface, family, &ports)?;
+    let device = read_device_info(iface, ports.mem_ap, family)?;
+    log::info!("device: {} ({}, {:?} flash, {} B sectors)", device, family.core, family.flash_controller, family.sector_size);
+    if let Some(expected) = &options.expected_part {
+        device.check_part(expected)?;
+    }
+    Ok(Connection { ports, family, core_state, reset_halt, device })
+}
+
+/// `check_status_bits` - warn of MDM-AP Status flags `family` does not implement,
+/// a sign of a wrong family selected by name
+fn check_status_bits<T: Transport + ?Sized>(iface: &mut T, family: &DeviceFamily, ports: &ApPorts) -> Result<(), Error> {
+    let status = iface.read_ap_register(ports.mdm_ap, MKE_MDM_STATUS)?;
+    let unexpected = status & !family.mdm_status_bits;
+    if unexpected != 0 {
+        log::warn!("MDM-AP Status {:#010X}: bits {:#010X} not implemented by {}", status, unexpected, family.name);
+    }
+    Ok(())
+}
+
+fn connect_strategy<T: Transport + ?Sized>(
+    iface: &mut T,
+    options: &ConnectOptions,
+    family: &DeviceFamily,
+    ports: &ApPorts,
+) -> Result<(CoreState, Option<ResetHalt>), Error> {
+    match options.strategy {
+        ConnectStrategy::An4835MdmReset => an4835_connect(iface, options, family, ports),
+        ConnectStrategy::HardwareReset => hardware_reset_connect(iface, options, family, ports),
+        ConnectStrategy::SoftwareReset => software_reset_connect(iface, options, family, ports),
+        ConnectStrategy::HotAttach => hot_attach_connect(iface, options, family, ports),
+        ConnectStrategy::CoreHoldRelease => core_hold_connect(iface, options, family, ports),
+        ConnectStrategy::WakeAndCatch => wake_and_catch_connect(iface, options, family, ports),
+    }
+}
+
+/// `an4835_connect` - "SWD connection steps" based on AN4835, over any `Transport`
+pub fn an4835_connect<T: Transport + ?Sized>(
+    iface: &mut T,
+    options: &ConnectOptions,
+    family: &DeviceFamily,
+    ports: &ApPorts,
+) -> Result<(CoreState, Option<ResetHalt>), Error> {
+
+    log::info!("MKE GENERAL INTERFACE : debug_mode_on based on AN4835");
+
+    /*  "SWD connection steps" based on AN4835  */
+    /* 1. init mdm ap reg, read current state */
+    let mut mdm_ap = read_mdm_ap(iface, options, family, ports)?;
+
+    /* 2. write the System Reset Request bit. Keep reset low and establish communication with the ARM DAP.  */
+    mdm_ap.mdm_ap_reset_keep(iface)?;
+
+    /* 3. The MDM-AP ID register can be read to verify that the connection is working correctly. */
+    check_idr(&mdm_ap, iface, family)?;
+
+    /* 4. The MDM-AP ID register can be read to verify that the connection is working correctly. */
+    mdm_ap.is_mdm_flash_ready(iface)?;
+
+    /* 5. Read the System Security bit to determine if security is enabled. If System Security = 0, then proceed. */                                                                             //
+    check_security(&mut mdm_ap, iface, options.mass_erase)?;
+    check_debug_disable(&mut mdm_ap, iface)?;
+
+    /* 6. Write the MDM-AP register to set the Debug Request bit */
+    mdm_ap.set_control_bit(iface, MdmApControlBit::DebugRequest)?;
+    mdm_ap.refresh_and_compare_mdm_ap(iface," DBG_REQ_BIT set ".to_string())?;
+
+    /* Try to write HALT bit on DHCSR reg ARM core */
+    // When the steps above have been completed, debugging or flash programming can be started.
+    dhcsr_halt(iface, ports.mem_ap, time::Duration::from_millis(500))?;
+    mdm_ap.refresh_mdm_ap(iface, true)?;
+    if options.vector_catch {
+        set_vector_catch(iface, ports.mem_ap, true)?;
+    }
+
+    /*  7. clear the System Reset Request bit in the MDM-AP control register. */
+    mdm_ap.mdm_ap_clear_reset_bit(iface)?;
+    wait_halted(&mut mdm_ap, iface)?;
+    mdm_ap.refresh_mdm_ap(iface, true)?;
+    let reset_halt = match options.vector_catch {
+        true => Some(read_reset_halt(iface, ports.mem_ap)?),
+        false => None,
+    };
+
+    let core_state = check_core_halted(iface, ports)?;
+
+    mdm_ap.refresh_mdm_ap(iface, true)?;
+
+    Ok((core_state, reset_halt))
+
+}
+
+/// `core_hold_connect` - AN4835 steps with reset released under MDM-AP Core Hold Reset
+///
+/// Firmware never runs: system initializes with the core held, DHCSR halt is written,
+/// then core hold is released and the core halts at once. Each step is checked by MDM-AP Status.
+pub fn core_hold_connect<T: Transport + ?Sized>(
+    iface: &mut T,
+    options: &ConnectOptions,
+    family: &DeviceFamily,
+    ports: &ApPorts,
+) -> Result<(CoreState, Option<ResetHalt>), Error> {
+
+    let mut mdm_ap = read_mdm_ap(iface, options, family, ports)?;
+    mdm_ap.mdm_ap_reset_keep(iface)?;
+    check_idr(&mdm_ap, iface, family)?;
+    mdm_ap.is_mdm_flash_ready(iface)?;
+    check_security(&mut mdm_ap, iface, options.mass_erase)?;
+    check_debug_disable(&mut mdm_ap, iface)?;
+
+    /* Core Hold Reset and Debug Request set while system reset is held */
+    mdm_ap.set_control_bit(iface, MdmApControlBit::CoreHold)?;
+    mdm_ap.set_control_bit(iface, MdmApControlBit::DebugRequest)?;
+    if options.vector_catch {
+        dhcsr_halt(iface, ports.mem_ap, time::Duration::from_millis(1))?;
+        set_vector_catch(iface, ports.mem_ap, true)?;
+    }
+
+    /* system leaves reset, core stays held - not running, not halted */
+    mdm_ap.mdm_ap_clear_reset_bit(iface)?;
+    let stats = mdm_ap.wait_reset_released(iface)?;
+    log::debug!("system reset released under core hold after {}us, {} polls", stats.elapsed.as_micros(), stats.polls);
+    check_core_held(&mdm_ap)?;
+
+    /* system initialized, halt request to the held core */
+    dhcsr_halt(iface, ports.mem_ap, time::Duration::from_millis(1))?;
+    mdm_ap.refresh_mdm_ap(iface, true)?;
+    check_core_held(&mdm_ap)?;
+
+    /* core leaves hold directly into debug halt */
+    mdm_ap.clear_control_bit(iface, MdmApControlBit::CoreHold)?;
+    let stats = mdm_ap.wait_core_halted(iface)?;
+    log::info!("MDM_AP halt_state set after core hold release, {}us, {} polls", stats.elapsed.as_micros(), stats.polls);
+    mdm_ap.refresh_mdm_ap(iface, true)?;
+    let reset_halt = match options.vector_catch {
+        true => Some(read_reset_halt(iface, ports.mem_ap)?),
+        false => None,
+    };
+
+    Ok((check_core_halted(iface, ports)?, reset_halt))
+}
+
+/// `check_core_held` - `Error::CoreHoldNotEffective` if MDM-AP reports the core halted while Core Hold Reset is set
+fn check_core_held(mdm_ap: &MdmAP) -> Result<(), Error> {
+    if mdm_ap.status.halt_state() {
+        return Err(Error::CoreHoldNotEffective {
+            status: mdm_ap.status.value(),
+            control: mdm_ap.control.to_u32(),
+        });
+    }
+    Ok(())
+}
+
+/// `wake_and_catch_connect` - repeat reset hold, debug request and DHCSR halt until the core is caught
+///
+/// System reset wakes the target from any low power mode, Debug Request halts the core as soon as
+/// it leaves reset. Firmware may still win the race and enter STOP / VLLS, then the attempt is
+/// repeated, up to `options.recovery_attempts`. Low power flags of MDM-AP Status seen after every
+/// failed attempt are logged and summarized in `Error::LowPowerRecoveryFailed`.
+pub fn wake_and_catch_connect<T: Transport + ?Sized>(
+    iface: &mut T,
+    options: &ConnectOptions,
+    family: &DeviceFamily,
+    ports: &ApPorts,
+) -> Result<(CoreState, Option<ResetHalt>), Error> {
+
+    let mut mdm_ap = read_mdm_ap(iface, options, family, ports)?;
+    log_low_power(0, &mdm_ap.status);
+    mdm_ap.mdm_ap_reset_keep(iface)?;
+    check_idr(&mdm_ap, iface, family)?;
+    mdm_ap.is_mdm_flash_ready(iface)?;
+    check_security(&mut mdm_ap, iface, options.mass_erase)?;
+    check_debug_disable(&mut mdm_ap, iface)?;
+
+    /* how many failed attempts saw each low power flag */
+    let mut observed = [0u32; 6];
+    for attempt in 1..=options.recovery_attempts {
+        match catch_attempt(&mut mdm_ap, iface, options, ports) {
+            Ok(halted) => {
+                log::info!("core caught at attempt {} of {}", attempt, options.recovery_attempts);
+                return Ok(halted);
+            }
+            Err(err @ (Error::TargetSecured | Error::MassEraseDisabled | Error::Unsupported(_) | Error::Io(_))) => {
+                return Err(err)
+            }
+            Err(err) => log::warn!("attempt {} : core not caught: {}", attempt, report(&err)),
+        }
+
+        /* MDM-AP stays accessible in low power modes, see what the firmware did */
+        if mdm_ap.refresh_mdm_ap(iface, false).is_ok() {
+            log_low_power(attempt, &mdm_ap.status);
+            for (count, (_, set)) in observed.iter_mut().zip(mdm_ap.status.low_power_fields()) {
+                *count += set as u32;
+            }
+        }
+        thread::sleep(options.recovery_retry_delay);
+    }
+
+    let fields = MdmApStatus::default().low_power_fields();
+    let observed = fields
+        .iter()
+        .zip(observed)
+        .filter(|(_, count)| *count > 0)
+        .map(|((name, _), count)| format!("{} x{}", name, count))
+        .collect::<Vec<_>>();
+    let observed = if observed.is_empty() { "no low power flags".to_string() } else { observed.join(", ") };
+    Err(Error::LowPowerRecoveryFailed { attempts: options.recovery_attempts, observed })
+}
+
+/// `catch_attempt` - one reset hold, debug request, DHCSR halt, reset release
+fn catch_attempt<T: Transport + ?Sized>(
+    mdm_ap: &mut MdmAP,
+    iface: &mut T,
+    options: &ConnectOptions,
+    ports: &ApPorts,
+) -> Result<(CoreState, Option<ResetHalt>), Error> {
+    mdm_ap.mdm_ap_reset_keep(iface)?;
+    mdm_ap.set_control_bit(iface, MdmApControlBit::DebugRequest)?;
+    dhcsr_halt(iface, ports.mem_ap, time::Duration::from_millis(1))?;
+    if options.vector_catch {
+        set_vector_catch(iface, ports.mem_ap, true)?;
+    }
+    mdm_ap.mdm_ap_clear_reset_bit(iface)?;
+    mdm_ap.wait_core_halted(iface)?;
+    let reset_halt = match options.vector_catch {
+        true => Some(read_reset_halt(iface, ports.mem_ap)?),
+        false => None,
+    };
+    Ok((check_core_halted(iface, ports)?, reset_halt))
+}
+
+/// `log_low_power` - MDM-AP low power flags seen at `attempt`
+fn log_low_power(attempt: u32, status: &MdmApStatus) {
+    let set: Vec<&str> = status
+        .low_power_fields()
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| *name)
+        .collect();
+    log::info!("attempt {} : MDM_AP low power flags [{}]", attempt, set.join(" "));
+}
+
+/// `hardware_reset_connect` - connect under probe NRST, halt by DHCSR, release NRST
+///
+/// NRST is asserted by `open_transport` before attach. It is released on failure too,
+/// the target is never left held in reset.
+pub fn hardware_reset_connect<T: Transport + ?Sized>(
+    iface: &mut T,
+    options: &ConnectOptions,
+    family: &DeviceFamily,
+    ports: &ApPorts,
+) -> Result<(CoreState, Option<ResetHalt>), Error> {
+
+    let held = hardware_reset_hold(iface, options, family, ports);
+    let released = iface.set_nrst(false);
+    let mut mdm_ap = held?;
+    released?;
+
+    /* mass erase leaves MDM-AP System Reset Request set, release it too */
+    if mdm_ap.control.sys_reset_request() {
+        mdm_ap.mdm_ap_clear_reset_bit(iface)?;
+    }
+    wait_halted(&mut mdm_ap, iface)?;
+    mdm_ap.refresh_mdm_ap(iface, true)?;
+    let reset_halt = match options.vector_catch {
+        true => Some(read_reset_halt(iface, ports.mem_ap)?),
+        false => None,
+    };
+
+    Ok((check_core_halted(iface, ports)?, reset_halt))
+}
+
+/// `hardware_reset_hold` - steps of `hardware_reset_connect` while NRST is held low
+fn hardware_reset_hold<T: Transport + ?Sized>(
+    iface: &mut T,
+    options: &ConnectOptions,
+    family: &DeviceFamily,
+    ports: &ApPorts,
+) -> Result<MdmAP, Error> {
+
+    let mut mdm_ap = read_mdm_ap(iface, options, family, ports)?;
+    mdm_ap.wait_for_status(
+        iface,
+        "hardware reset assert",
+        |mdm_ap| !mdm_ap.status.system_reset(),
+        mdm_ap.timeouts.reset,
+        mdm_ap.timeouts.poll_interval,
+    )?;
+    check_idr(&mdm_ap, iface, family)?;
+    mdm_ap.is_mdm_flash_ready(iface)?;
+    check_security(&mut mdm_ap, iface, options.mass_erase)?;
+    check_debug_disable(&mut mdm_ap, iface)?;
+
+    dhcsr_halt(iface, ports.mem_ap, time::Duration::from_millis(1))?;
+    if options.vector_catch {
+        set_vector_catch(iface, ports.mem_ap, true)?;
+    }
+    Ok(mdm_ap)
+}
+
+/// `software_reset_connect` - halt core, reset system by AIRCR SYSRESETREQ, halt again
+pub fn software_reset_connect<T: Transport + ?Sized>(
+    iface: &mut T,
+    options: &ConnectOptions,
+    family: &DeviceFamily,
+    ports: &ApPorts,
+) -> Result<(CoreState, Option<ResetHalt>), Error> {
+
+    let mut mdm_ap = read_mdm_ap(iface, options, family, ports)?;
+    check_idr(&mdm_ap, iface, family)?;
+    check_security(&mut mdm_ap, iface, options.mass_erase)?;
+    check_debug_disable(&mut mdm_ap, iface)?;
+    if mdm_ap.control.sys_reset_request() {
+        mdm_ap.mdm_ap_clear_reset_bit(iface)?;
+    }
+
+    dhcsr_halt(iface, ports.mem_ap, time::Duration::from_millis(1))?;
+    if options.vector_catch {
+        set_vector_catch(iface, ports.mem_ap, true)?;
+    }
+    /* write may be not acked, system is going to reset - never retried, a retry would reset it again */
+    if let Err(err) = iface.write_mem_word_no_retry(ports.mem_ap, AIRCR_ADDRESS, AIRCR_VECTKEY | AIRCR_SYSRESETREQ) {
+        log::debug!("AIRCR SYSRESETREQ write : {}", err);
+    }
+    let _ = iface.flush();
+    thread::sleep(time::Duration::from_millis(10));
+
+    /* C_HALT survives system reset, write it again in case the core run before it was seen */
+    dhcsr_halt(iface, ports.mem_ap, time::Duration::from_millis(1))?;
+    wait_halted(&mut mdm_ap, iface)?;
+    mdm_ap.refresh_mdm_ap(iface, true)?;
+    let reset_halt = match options.vector_catch {
+        true => Some(read_reset_halt(iface, ports.mem_ap)?),
+        false => None,
+    };
+
+    Ok((check_core_halted(iface, ports)?, reset_halt))
+}
+
+/// `hot_attach_connect` - halt the running core, no reset
+pub fn hot_attach_connect<T: Transport + ?Sized>(
+    iface: &mut T,
+    options: &ConnectOptions,
+    family: &DeviceFamily,
+    ports: &ApPorts,
+) -> Result<(CoreState, Option<ResetHalt>), Error> {
+
+    if options.vector_catch {
+        log::warn!("vector catch ignored, hot attach does not reset the target");
+    }
+
+    let mut mdm_ap = read_mdm_ap(iface, options, family, ports)?;
+    check_idr(&mdm_ap, iface, family)?;
+    check_security(&mut mdm_ap, iface, options.mass_erase)?;
+    check_debug_disable(&mut mdm_ap, iface)?;
+    if mdm_ap.control.sys_reset_request() {
+        mdm_ap.mdm_ap_clear_reset_bit(iface)?;
+    }
+
+    dhcsr_halt(iface, ports.mem_ap, time::Duration::from_millis(1))?;
+    wait_halted(&mut mdm_ap, iface)?;
+    mdm_ap.refresh_mdm_ap(iface, true)?;
+
+    Ok((check_core_halted(iface, ports)?, None))
+}
+
+/// `read_mdm_ap` - current MDM-AP at `ports.mdm_ap` with `options.timeouts` and control bits of `family`
+fn read_mdm_ap<T: Transport + ?Sized>(
+    iface: &mut T,
+    options: &ConnectOptions,
+    family: &DeviceFamily,
+    ports: &ApPorts,
+) -> Result<MdmAP, Error> {
+    let mut mdm_ap = MdmAP::read_mdm_ap_register_at(iface, ports.mdm_ap, false)?;
+    mdm_ap.timeouts = options.timeouts;
+    mdm_ap.control_bits = family.mdm_control_bits;
+    Ok(mdm_ap)
+}
+
+/// `check_idr` - verify MDM-AP IDR, mismatch is only a warning
+fn check_idr<T: Transport + ?Sized>(mdm_ap: &MdmAP, iface: &mut T, family: &DeviceFamily) -> Result<(), Error> {
+    match mdm_ap.check_mdm_ap_idr_value(iface, family.mdm_ap_idr) {
+        Ok(idr_reg) => log::info!( "MKE ID Register {}", &format!("{:#06X} OK ", idr_reg )),
+        Err(err @ Error::IdrMismatch { .. }) => log::warn!( "{}", err ),
+        Err(err) => return Err(err),
+    }
+    Ok(())
+}
+
+/// `check_security` - `Error::TargetSecured` if secured, or unsecure by mass erase if allowed.
+/// System reset is still held after mass erase, caller continue with debug request.
+fn check_security<T: Transport + ?Sized>(mdm_ap: &mut MdmAP, iface: &mut T, mass_erase: bool) -> Result<(), Error> {
+    mdm_ap.refresh_mdm_ap(iface, false)?;
+    if mdm_ap.status.security() {
+        log::warn!("Target connected, but secured");
+        if !mass_erase {
+            /* didn't try to halt core, user can try mass erase chip if decide (--mass-erase) */
+            log::warn!("Target is secured, for unsecure mass erase");
+            return Err(Error::TargetSecured);
+        }
+        match mdm_ap.mass_erase(iface)? {
+            MassEraseOutcome::Unsecured => log::info!("Mass erase done, target unsecured"),
+            MassEraseOutcome::StillSecured => return Err(Error::TargetSecured),
+        }
+    }
+    Ok(())
+}
+
+/// `check_debug_disable` - clear MDM-AP Debug Disable if set, `Error::DebugDisabled` if it stays set
+fn check_debug_disable<T: Transport + ?Sized>(mdm_ap: &mut MdmAP, iface: &mut T) -> Result<(), Error> {
+    mdm_ap.read_control(iface)?;
+    if mdm_ap.control.debug_disable() {
+        log::warn!("MDM_AP Debug Disable is set, clearing");
+        mdm_ap.set_debug_disable(iface, false)?;
+        log::info!("MDM_AP Debug Disable cleared");
+    }
+    Ok(())
+}
+
+/// `dhcsr_halt` - write C_HALT | C_DEBUGEN to DHCSR, read it back after `settle`
+fn dhcsr_halt<T: Transport + ?Sized>(iface: &mut T, mem_ap: u8, settle: time::Duration) -> Result<(), Error> {
+    let mut dhcsr = Dhcsr(0);
+    dhcsr.set_c_halt(true);
+    dhcsr.set_c_debugen(true);
+    dhcsr.enable_write();
+
+    let before = CoreState::read(iface, mem_ap)?;
+    iface.write_mem_word(mem_ap, Dhcsr::get_mmio_address(), dhcsr.into())?;
+    iface.flush()?;
+
+    thread::sleep(settle);
+    let after = CoreState::read(iface, mem_ap)?;
+    log::debug!("core before halt write: {}, after: {}", before, after);
+    Ok(())
+}
+
+/// `check_core_halted` - final `CoreState`, error with the diagnosis if `S_HALT` is not set
+///
+/// `Error::DebugDisabled` - DBG_DIS got set, `Error::DebugRequestIgnored` - DBG_REQ set but MDM-AP
+/// reports no halt, otherwise `Error::DhcsrHaltFailed`.
+fn check_core_halted<T: Transport + ?Sized>(iface: &mut T, ports: &ApPorts) -> Result<CoreState, Error> {
+    let core_state = CoreState::read(iface, ports.mem_ap)?;
+    log::info!("core state: {}", core_state);
+    if core_state.halted {
+        return Ok(core_state);
+    }
+
+    let mdm_ap = MdmAP::read_mdm_ap_register_at(iface, ports.mdm_ap, false)?;
+    if mdm_ap.control.debug_disable() {
+        return Err(Error::DebugDisabled { control: mdm_ap.control.to_u32() });
+    }
+    if mdm_ap.control.debug_request() && !mdm_ap.status.halt_state() {
+        return Err(Error::DebugRequestIgnored {
+            status: mdm_ap.status.value(),
+            control: mdm_ap.control.to_u32(),
+        });
+    }
+    Err(Error::DhcsrHaltFailed { dhcsr: core_state.dhcsr })
+}
+
+/// `wait_halted` - wait MDM-AP `halt_state`, only report if it is not set
+fn wait_halted<T: Transport + ?Sized>(mdm_ap: &mut MdmAP, iface: &mut T) -> Result<(), Error> {
+    match mdm_ap.wait_core_halted(iface) {
+        Ok(stats) => log::info!("MDM_AP halt_state set after {}us, {} polls", stats.elapsed.as_micros(), stats.polls),
+        Err(Error::Timeout { elapsed, .. }) => log::warn!("MDM_AP halt_state not set after {}ms", elapsed.as_millis()),
+        Err(err) => return Err(err),
+    }
+    Ok(())
+}
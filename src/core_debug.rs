@@ -0,0 +1,155 @@
+use super::*;
+
+use std::fmt;
+
+/// ARMv6-M `DEMCR` - Debug Exception and Monitor Control Register
+pub const DEMCR_ADDRESS: u64 = 0xE000_EDFC;
+/// `DEMCR` `VC_CORERESET` - halt on the first instruction after reset
+pub const DEMCR_VC_CORERESET: u32 = 1 << 0;
+
+/// ARMv6-M `DCRSR` / `DCRDR` - Debug Core Register Selector / Data
+pub const DCRSR_ADDRESS: u64 = 0xE000_EDF4;
+pub const DCRDR_ADDRESS: u64 = 0xE000_EDF8;
+/// `DCRSR` `REGWnR` - 1 write, 0 read core register
+pub const DCRSR_REGWNR: u32 = 1 << 16;
+/// `DCRSR` `REGSEL` of SP and PC (DebugReturnAddress)
+pub const CORE_REG_SP: u32 = 13;
+pub const CORE_REG_PC: u32 = 15;
+
+/// `DHCSR` `S_REGRDY` - core register transfer done
+pub const DHCSR_S_REGRDY: u32 = 1 << 16;
+
+/// `VECTOR_TABLE_ADDRESS` - VTOR is 0 after reset, initial SP at +0, reset vector at +4
+pub const VECTOR_TABLE_ADDRESS: u64 = 0x0000_0000;
+
+/// `CORE_REG_TIMEOUT_MS` - max wait of `S_REGRDY`
+pub const CORE_REG_TIMEOUT_MS: u64 = 10;
+
+/// `ResetHalt` - where the core stopped after reset with `VC_CORERESET` armed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResetHalt {
+    pub pc: u32,
+    pub sp: u32,
+    /// reset vector from vector table, Thumb bit cleared
+    pub reset_vector: u32,
+    /// initial SP from vector table
+    pub initial_sp: u32,
+}
+
+impl ResetHalt {
+    /// `at_reset_vector` - no firmware instruction was executed before the halt
+    pub fn at_reset_vector(&self) -> bool {
+        self.pc == self.reset_vector && self.sp == self.initial_sp
+    }
+}
+
+/// `set_vector_catch` - arm / disarm `DEMCR` `VC_CORERESET`, other `DEMCR` bits are kept
+pub fn set_vector_catch<T: Transport + ?Sized>(iface: &mut T, mem_ap: u8, enable: bool) -> Result<(), Error> {
+    let demcr = iface.read_mem_word(mem_ap, DEMCR_ADDRESS)?;
+    let demcr = if enable { demcr | DEMCR_VC_CORERESET } else { demcr & !DEMCR_VC_CORERESET };
+    iface.write_mem_word(mem_ap, DEMCR_ADDRESS, demcr)?;
+    iface.flush()
+}
+
+/// `read_core_register` - read core register `regsel` by `DCRSR` / `DCRDR`, core must be halted
+pub fn read_core_register<T: Transport + ?Sized>(iface: &mut T, mem_ap: u8, regsel: u32) -> Result<u32, Error> {
+    iface.write_mem_word(mem_ap, DCRSR_ADDRESS, regsel & 0x1F)?;
+
+    let start = time::Instant::now();
+    let timeout = time::Duration::from_millis(CORE_REG_TIMEOUT_MS);
+    loop {
+        let dhcsr = iface.read_mem_word(mem_ap, Dhcsr::get_mmio_address())?;
+        if dhcsr & DHCSR_S_REGRDY != 0 {
+            break;
+        }
+        if start.elapsed() >= timeout {
+            return Err(Error::Timeout { operation: "core register read (S_REGRDY)", elapsed: start.elapsed() });
+        }
+    }
+    iface.read_mem_word(mem_ap, DCRDR_ADDRESS)
+}
+
+/// `read_reset_halt` - read PC, SP and vector table of halted core, disarm `VC_CORERESET`
+///
+/// `Error::VectorCatchMissed` if the core is not at the reset vector, some firmware run first.
+pub fn read_reset_halt<T: Transport + ?Sized>(iface: &mut T, mem_ap: u8) -> Result<ResetHalt, Error> {
+    let halt = ResetHalt {
+        pc: read_core_register(iface, mem_ap, CORE_REG_PC)?,
+        sp: read_core_register(iface, mem_ap, CORE_REG_SP)?,
+        reset_vector: iface.read_mem_word(mem_ap, VECTOR_TABLE_ADDRESS + 4)? & !1,
+        initial_sp: iface.read_mem_word(mem_ap, VECTOR_TABLE_ADDRESS)?,
+    };
+    set_vector_catch(iface, mem_ap, false)?;
+
+    log::info!(
+        "core halted at PC {:#010X} SP {:#010X}, reset vector {:#010X} initial SP {:#010X}",
+        halt.pc, halt.sp, halt.reset_vector, halt.initial_sp
+    );
+    if !halt.at_reset_vector() {
+        return Err(Error::VectorCatchMissed { pc: halt.pc, reset_vector: halt.reset_vector });
+    }
+    Ok(halt)
+}
+
+/// `CoreState` - Cortex-M core state decoded from `DHCSR`
+///
+/// `S_RESET_ST` and `S_RETIRE_ST` are sticky, cleared by the `DHCSR` read which gave this state.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct CoreState {
+    pub dhcsr: u32,
+    /// `C_DEBUGEN`
+    pub debug_enabled: bool,
+    /// `S_HALT`
+    pub halted: bool,
+    /// `S_SLEEP`
+    pub sleeping: bool,
+    /// `S_LOCKUP`
+    pub locked_up: bool,
+    /// `S_RESET_ST` - core reset since the last `DHCSR` read
+    pub reset_seen: bool,
+    /// `S_RETIRE_ST` - instruction retired since the last `DHCSR` read
+    pub retired: bool,
+    /// `S_REGRDY`
+    pub register_ready: bool,
+}
+
+impl CoreState {
+    pub fn from_dhcsr(dhcsr: u32) -> Self {
+        let dhcsr_reg = Dhcsr(dhcsr);
+        Self {
+            dhcsr,
+            debug_enabled: dhcsr_reg.c_debugen(),
+            halted: dhcsr_reg.s_halt(),
+            sleeping: dhcsr_reg.s_sleep(),
+            locked_up: dhcsr_reg.s_lockup(),
+            reset_seen: dhcsr_reg.s_reset_st(),
+            retired: dhcsr_reg.s_retire_st(),
+            register_ready: dhcsr_reg.s_regrdy(),
+        }
+    }
+
+    /// `read` - read `DHCSR` over AHB MEM-AP `mem_ap`
+    pub fn read<T: Transport + ?Sized>(iface: &mut T, mem_ap: u8) -> Result<Self, Error> {
+        let dhcsr = iface.read_mem_word(mem_ap, Dhcsr::get_mmio_address())?;
+        Ok(Self::from_dhcsr(dhcsr))
+    }
+
+    pub fn fields(&self) -> [(&'static str, bool); 7] {
+        [
+            ("debug_enabled", self.debug_enabled),
+            ("halted", self.halted),
+            ("sleeping", self.sleeping),
+            ("locked_up", self.locked_up),
+            ("reset_seen", self.reset_seen),
+            ("retired", self.retired),
+            ("register_ready", self.register_ready),
+        ]
+    }
+}
+
+impl fmt::Display for CoreState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DHCSR {:#010X}", self.dhcsr)?;
+        write_set_fields(f, &self.fields())
+    }
+}
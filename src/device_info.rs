@@ -0,0 +1,196 @@
+use super::*;
+
+use std::fmt;
+
+/// Kinetis `SIM` registers, family specific addresses are in `DeviceFamily`
+pub const SIM_SDID_ADDRESS: u64 = 0x4004_8024;
+pub const SIM_FCFG1_ADDRESS: u64 = 0x4004_804C;
+pub const SIM_UIDH_ADDRESS: u64 = 0x4004_8054;
+pub const SIM_UIDMH_ADDRESS: u64 = 0x4004_8058;
+pub const SIM_UIDML_ADDRESS: u64 = 0x4004_805C;
+pub const SIM_UIDL_ADDRESS: u64 = 0x4004_8060;
+
+/// `DeviceInfo` - part identification from `SIM_SDID`, `SIM_FCFG1` and unique ID registers,
+/// decoded by `DeviceFamily::id_layout`, `None` - the family has no such field
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// `DeviceFamily::name`
+    pub family: &'static str,
+    pub naming: PartNaming,
+    /// `SIM_SDID`, `SIM_SRSID` on KE0x
+    pub sdid: u32,
+    pub fcfg1: u32,
+    /// `SDID[FAMID]` - KEx family, 1 - KE1x
+    pub family_id: Option<u8>,
+    /// `SDID[SUBFAMID]` - KE1x subfamily, 4 - KE14, 5 - KE15, 6 - KE16
+    pub subfamily_id: Option<u8>,
+    /// `SDID[SERIESID]`
+    pub series_id: Option<u8>,
+    /// `SDID[PINID]` decoded, `None` also for reserved / custom package
+    pub pin_count: Option<u16>,
+    /// `SDID[DIEID]`
+    pub die_id: Option<u8>,
+    /// `SDID[REVID]`
+    pub revision: Option<u8>,
+    /// `SDID[SRAMSIZE]` decoded
+    pub sram_size: Option<u32>,
+    /// `FCFG1[PFSIZE]` decoded, `None` also for reserved encoding
+    pub flash_size: Option<u32>,
+    /// `UIDH:UIDMH:UIDML:UIDL`, `None` - family without unique ID registers
+    pub unique_id: Option<u128>,
+}
+
+impl DeviceInfo {
+    /// `parse` - decode `sdid` / `fcfg1` by `family.id_layout`, `fcfg1` is ignored if the family has none
+    pub fn parse(family: &DeviceFamily, sdid: u32, fcfg1: u32, unique_id: Option<u128>) -> Self {
+        let layout = family.id_layout;
+        let field = |field: Option<BitField>| field.map(|field| field.get(sdid) as u8);
+        let pin_id = layout.pin_id.map(|field| field.get(sdid));
+        let pfsize = match family.fcfg1_address {
+            Some(_) => layout.flash_size.map(|field| field.get(fcfg1)),
+            None => None,
+        };
+        Self {
+            family: family.name,
+            naming: family.naming,
+            sdid,
+            fcfg1,
+            family_id: field(layout.family_id),
+            subfamily_id: field(layout.subfamily_id),
+            series_id: field(layout.series_id),
+            pin_count: pin_id.and_then(|pin_id| {
+                layout.pin_counts.iter().find(|(value, _)| *value == pin_id).map(|(_, pins)| *pins)
+            }),
+            die_id: field(layout.die_id),
+            revision: field(layout.revision),
+            sram_size: layout.sram_size.map(|field| 512 << field.get(sdid)),
+            flash_size: match pfsize {
+                Some(0b0000) => Some(8 * 1024),
+                Some(0b0001) => Some(16 * 1024),
+                Some(0b0011) => Some(32 * 1024),
+                Some(0b0101) => Some(64 * 1024),
+                Some(0b0111) => Some(128 * 1024),
+                Some(0b1001) => Some(256 * 1024),
+                Some(0b1011) => Some(512 * 1024),
+                _ => None,
+            },
+            unique_id,
+        }
+    }
+
+    /// `part_number` - orderable part name without package / temperature suffix, e.g. `KE14Z256`, `S32K144`,
+    /// flash size is left out if not reported, e.g. `KE02Z`
+    pub fn part_number(&self) -> String {
+        let id = |field: Option<u8>| field.map(|value| value.to_string()).unwrap_or("?".to_string());
+        match self.naming {
+            PartNaming::Kinetis { prefix, core } => {
+                let flash_kb = self.flash_size.map(|size| (size / 1024).to_string()).unwrap_or_default();
+                format!("{}{}{}{}{}", prefix, id(self.family_id), id(self.subfamily_id), core, flash_kb)
+            }
+            PartNaming::S32k => format!("S32K{}{}{}", id(self.family_id), id(self.subfamily_id), id(self.series_id)),
+        }
+    }
+
+    /// `check_part` - `Error::PartMismatch` if this is not the `expected` part, e.g. `KE14Z256`,
+    /// a part without reported flash size matches only the name without it, e.g. `KE02Z`
+    pub fn check_part(&self, expected: &str) -> Result<(), Error> {
+        let found = self.part_number();
+        if !found.eq_ignore_ascii_case(expected) {
+            return Err(Error::PartMismatch {
+                expected: expected.to_string(),
+                found: match self.flash_size {
+                    Some(_) => found,
+                    None => format!("{} (flash size not reported)", found),
+                },
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}, SDID {:#010X}", self.part_number(), self.family, self.sdid)?;
+        if let Some(pins) = self.pin_count {
+            write!(f, ", {} pins", pins)?;
+        }
+        if let Some(series) = self.series_id {
+            write!(f, ", series {}", series)?;
+        }
+        if let Some(die) = self.die_id {
+            write!(f, ", die {}", die)?;
+        }
+        if let Some(revision) = self.revision {
+            write!(f, ", rev {}", revision)?;
+        }
+        if let Some(size) = self.sram_size {
+            write!(f, ", SRAM {} KB", size / 1024)?;
+        }
+        if let Some(size) = self.flash_size {
+            write!(f, ", flash {} KB", size / 1024)?;
+        }
+        match self.unique_id {
+            Some(unique_id) => write!(f, "), UID {:032X}", unique_id),
+            None => write!(f, ")"),
+        }
+    }
+}
+
+/// `read_device_info` - read `DeviceInfo` of `family` over AHB MEM-AP `mem_ap`, target must be unsecured
+pub fn read_device_info<T: Transport + ?Sized>(
+    iface: &mut T,
+    mem_ap: u8,
+    family: &DeviceFamily,
+) -> Result<DeviceInfo, Error> {
+    let sdid = iface.read_mem_word(mem_ap, family.sdid_address)?;
+    let fcfg1 = match family.fcfg1_address {
+        Some(address) => iface.read_mem_word(mem_ap, address)?,
+        None => 0,
+    };
+    let mut unique_id = None;
+    for &address in family.uid_addresses {
+        let word = iface.read_mem_word(mem_ap, address)? as u128;
+        unique_id = Some((unique_id.unwrap_or(0) << 32) | word);
+    }
+    Ok(DeviceInfo::parse(family, sdid, fcfg1, unique_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sim_device() -> DeviceInfo {
+        DeviceInfo::parse(find_device_family("KE1xZ").unwrap(), SIM_SDID, SIM_FCFG1, None)
+    }
+
+    #[test]
+    fn ke1xz_sdid_and_fcfg1() {
+        let device = sim_device();
+        assert_eq!(device.part_number(), "KE14Z256");
+        assert_eq!(device.pin_count, Some(64));
+        assert_eq!(device.series_id, Some(1));
+        assert_eq!(device.sram_size, Some(32 * 1024));
+        assert_eq!(device.flash_size, Some(256 * 1024));
+    }
+
+    #[test]
+    fn check_part_ignores_case() {
+        let device = sim_device();
+        assert!(device.check_part("ke14z256").is_ok());
+        assert!(matches!(device.check_part("KE14Z128"), Err(Error::PartMismatch { .. })));
+    }
+
+    #[test]
+    fn check_part_without_flash_size_is_not_a_prefix_match() {
+        let mut device = sim_device();
+        device.flash_size = None;
+        assert!(device.check_part("KE14Z").is_ok());
+        match device.check_part("KE14Z256") {
+            Err(Error::PartMismatch { expected, found }) => {
+                assert_eq!(expected, "KE14Z256");
+                assert_eq!(found, "KE14Z (flash size not reported)");
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
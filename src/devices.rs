@@ -0,0 +1,442 @@
+use super::*;
+
+/// `MDM_STATUS_ALL_BITS` - every MDM-AP Status flag decoded by `MdmApStatus`
+pub const MDM_STATUS_ALL_BITS: u32 = 0x0007_07EF;
+/// `MDM_STATUS_NO_LLS_VLLS` - parts without LLS / VLLS modes have no LLS / VLLS exit flags
+pub const MDM_STATUS_NO_LLS_VLLS: u32 = MDM_STATUS_ALL_BITS
+    & !(MKE_MDM_STATUS_LLS_EXIT_BIT | MKE_MDM_STATUS_VLLS_EXIT_BIT);
+/// `MDM_STATUS_NO_VLP` - KE0x: STOP / WAIT only, no VLPx, LLS, VLLS flags
+pub const MDM_STATUS_NO_VLP: u32 = MDM_STATUS_NO_LLS_VLLS
+    & !(MKE_MDM_STATUS_LP_ENABLED_BIT | MKE_MDM_STATUS_VLP_MODE_BIT);
+
+/// `MDM_CONTROL_ALL_BITS` - MDM-AP Control requests up to LLS / VLLSx Status Acknowledge,
+/// bits 5..7 (VLLDBGREQ, VLLDBGACK, LLSSTATACK) only on parts with VLLSx debug
+pub const MDM_CONTROL_ALL_BITS: u32 = 0x0000_00FF;
+/// `MDM_CONTROL_NO_VLLS` - mass erase, debug disable / request, reset request, core hold
+pub const MDM_CONTROL_NO_VLLS: u32 = 0x0000_001F;
+
+/// `MDM_IDR_CM0P` / `MDM_IDR_CM4` - MDM-AP IDR of Cortex-M0+ and Cortex-M4 Kinetis parts
+pub const MDM_IDR_CM0P: u32 = IDR_REG_CHECK_VALUE;
+pub const MDM_IDR_CM4: u32 = 0x001C_0000;
+
+/// `FlashController` - flash memory module, defines the command set and flash config layout
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlashController {
+    Ftmrh,
+    Ftmre,
+    Ftfa,
+    Ftfe,
+    Ftfc,
+}
+
+/// `PartNaming` - how `DeviceInfo::part_number` is built from SDID fields
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PartNaming {
+    /// `<prefix><FAMID><SUBFAMID><core><flash KB>`, e.g. `KE14Z256`, `KL25Z128`
+    Kinetis { prefix: &'static str, core: char },
+    /// `S32K<GENERATION><SUBSERIES><DERIVATIVE>`, e.g. `S32K144`
+    S32k,
+}
+
+/// `BitField` - `width` bits of a register from bit `shift`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BitField {
+    pub shift: u8,
+    pub width: u8,
+}
+
+impl BitField {
+    pub const fn new(shift: u8, width: u8) -> Self {
+        Self { shift, width }
+    }
+
+    pub fn get(&self, value: u32) -> u32 {
+        (value >> self.shift) & ((1u32 << self.width) - 1)
+    }
+}
+
+/// `IdLayout` - fields of the family identification register and `SIM_FCFG1`,
+/// `None` - the family has no such field
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IdLayout {
+    /// `FAMID`, S32K `GENERATION`
+    pub family_id: Option<BitField>,
+    /// `SUBFAMID`, S32K `SUBSERIES`
+    pub subfamily_id: Option<BitField>,
+    /// `SERIESID`, S32K `DERIVATE`
+    pub series_id: Option<BitField>,
+    /// `PINID` / `PACKAGE` and its `(value, pin count)` encodings
+    pub pin_id: Option<BitField>,
+    pub pin_counts: &'static [(u32, u16)],
+    pub die_id: Option<BitField>,
+    pub revision: Option<BitField>,
+    /// `SRAMSIZE`, `512 << value` bytes
+    pub sram_size: Option<BitField>,
+    /// `FCFG1[PFSIZE]`
+    pub flash_size: Option<BitField>,
+}
+
+/// `KINETIS_ID_LAYOUT` - `SIM_SDID` / `SIM_FCFG1` of KE1x and KL2x
+pub const KINETIS_ID_LAYOUT: IdLayout = IdLayout {
+    family_id: Some(BitField::new(28, 4)),
+    subfamily_id: Some(BitField::new(24, 4)),
+    series_id: Some(BitField::new(20, 4)),
+    pin_id: Some(BitField::new(0, 4)),
+    pin_counts: &[(0, 16), (1, 24), (2, 32), (3, 36), (4, 48), (5, 64), (6, 80), (8, 100)],
+    die_id: Some(BitField::new(7, 5)),
+    revision: Some(BitField::new(12, 4)),
+    sram_size: Some(BitField::new(16, 4)),
+    flash_size: Some(BitField::new(24, 4)),
+};
+
+/// `S32K_ID_LAYOUT` - S32K1xx `SIM_SDID`, RAMSIZE depends on the derivative and is not decoded
+pub const S32K_ID_LAYOUT: IdLayout = IdLayout {
+    family_id: Some(BitField::new(28, 4)),
+    subfamily_id: Some(BitField::new(24, 4)),
+    series_id: Some(BitField::new(20, 4)),
+    pin_id: Some(BitField::new(8, 4)),
+    pin_counts: &[(2, 48), (3, 64), (4, 100), (6, 144), (7, 176), (8, 100)],
+    die_id: None,
+    revision: Some(BitField::new(12, 4)),
+    sram_size: None,
+    flash_size: Some(BitField::new(24, 4)),
+};
+
+/// `KE0X_ID_LAYOUT` - KE0x `SIM_SRSID`, no series, die, SRAM size, no `FCFG1`
+pub const KE0X_ID_LAYOUT: IdLayout = IdLayout {
+    family_id: Some(BitField::new(28, 4)),
+    subfamily_id: Some(BitField::new(24, 4)),
+    series_id: None,
+    pin_id: Some(BitField::new(16, 4)),
+    pin_counts: &[(0, 8), (1, 16), (2, 20), (3, 24), (4, 32), (5, 44), (6, 48), (7, 64), (8, 80), (10, 100)],
+    die_id: None,
+    revision: Some(BitField::new(20, 4)),
+    sram_size: None,
+    flash_size: None,
+};
+
+/// `MemoryRegion` - `[start, end)` address range
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// `DeviceFamily` - one entry of `DEVICE_FAMILIES`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DeviceFamily {
+    pub name: &'static str,
+    pub core: &'static str,
+    pub mdm_ap_idr: u32,
+    /// MDM-AP Status bits implemented by the family
+    pub mdm_status_bits: u32,
+    /// MDM-AP Control bits implemented by the family, other bits are written 0
+    pub mdm_control_bits: u32,
+    /// program flash and SRAM of the largest family member
+    pub flash: MemoryRegion,
+    pub sram: MemoryRegion,
+    /// operating supply range, default of the target voltage check
+    pub supply: VoltageRange,
+    pub flash_controller: FlashController,
+    pub sector_size: u32,
+    /// flash configuration field and offset of FSEC in it
+    pub flash_config_address: u64,
+    pub fsec_offset: u64,
+    /// family identification register (`SIM_SDID`, `SIM_SRSID` on KE0x), matched by `sdid_mask` / `sdid_value`
+    pub sdid_address: u64,
+    pub sdid_mask: u32,
+    pub sdid_value: u32,
+    /// `SIM_FCFG1`, `None` - flash size is not reported
+    pub fcfg1_address: Option<u64>,
+    /// SDID / FCFG1 fields of the family
+    pub id_layout: &'static IdLayout,
+    /// unique ID registers, most significant word first
+    pub uid_addresses: &'static [u64],
+    pub naming: PartNaming,
+}
+
+impl DeviceFamily {
+    /// `fsec_address` - address of the flash security byte
+    pub fn fsec_address(&self) -> u64 {
+        self.flash_config_address + self.fsec_offset
+    }
+
+    pub fn sdid_matches(&self, sdid: u32) -> bool {
+        sdid & self.sdid_mask == self.sdid_value
+    }
+}
+
+const KINETIS_UID: [u64; 4] = [SIM_UIDH_ADDRESS, SIM_UIDMH_ADDRESS, SIM_UIDML_ADDRESS, SIM_UIDL_ADDRESS];
+const KL2X_UID: [u64; 3] = [SIM_UIDMH_ADDRESS, SIM_UIDML_ADDRESS, SIM_UIDL_ADDRESS];
+
+const KE0X_SRSID_ADDRESS: u64 = 0x4004_8000;
+const KE0X_FLASH_CONFIG: u64 = 0x0000_0400;
+
+/// `DEVICE_FAMILIES` - parts sharing the Kinetis MDM-AP
+///
+/// Order matters for `detect_device_family`: families with the same MDM-AP IDR are tried in
+/// table order by their SDID match, KE0x (SRSID, weakest match) last.
+pub const DEVICE_FAMILIES: [DeviceFamily; 7] = [
+    DeviceFamily {
+        name: "KE1xZ",
+        core: "Cortex-M0+",
+        mdm_ap_idr: MDM_IDR_CM0P,
+        mdm_status_bits: MDM_STATUS_NO_LLS_VLLS,
+        mdm_control_bits: MDM_CONTROL_NO_VLLS,
+        flash: MemoryRegion { start: 0x0000_0000, end: 0x0004_0000 },
+        sram: MemoryRegion { start: 0x1FFF_E000, end: 0x2000_6000 },
+        supply: KE1X_VOLTAGE_RANGE,
+        flash_controller: FlashController::Ftfe,
+        sector_size: 2048,
+        flash_config_address: 0x0000_0400,
+        fsec_offset: 0xC,
+        sdid_address: SIM_SDID_ADDRESS,
+        sdid_mask: 0xF0F0_0000,
+        sdid_value: 0x1010_0000,
+        fcfg1_address: Some(SIM_FCFG1_ADDRESS),
+        id_layout: &KINETIS_ID_LAYOUT,
+        uid_addresses: &KINETIS_UID,
+        naming: PartNaming::Kinetis { prefix: "KE", core: 'Z' },
+    },
+    DeviceFamily {
+        name: "KL2x",
+        core: "Cortex-M0+",
+        mdm_ap_idr: MDM_IDR_CM0P,
+        mdm_status_bits: MDM_STATUS_ALL_BITS,
+        mdm_control_bits: MDM_CONTROL_ALL_BITS,
+        flash: MemoryRegion { start: 0x0000_0000, end: 0x0004_0000 },
+        sram: MemoryRegion { start: 0x1FFF_8000, end: 0x2000_6000 },
+        supply: KL2X_VOLTAGE_RANGE,
+        flash_controller: FlashController::Ftfa,
+        sector_size: 1024,
+        flash_config_address: 0x0000_0400,
+        fsec_offset: 0xC,
+        sdid_address: SIM_SDID_ADDRESS,
+        sdid_mask: 0xF0F0_0000,
+        sdid_value: 0x2010_0000,
+        fcfg1_address: Some(SIM_FCFG1_ADDRESS),
+        id_layout: &KINETIS_ID_LAYOUT,
+        uid_addresses: &KL2X_UID,
+        naming: PartNaming::Kinetis { prefix: "KL", core: 'Z' },
+    },
+    DeviceFamily {
+        name: "KE1xF",
+        core: "Cortex-M4F",
+        mdm_ap_idr: MDM_IDR_CM4,
+        mdm_status_bits: MDM_STATUS_NO_LLS_VLLS,
+        mdm_control_bits: MDM_CONTROL_NO_VLLS,
+        flash: MemoryRegion { start: 0x0000_0000, end: 0x0008_0000 },
+        sram: MemoryRegion { start: 0x1FFF_8000, end: 0x2000_8000 },
+        supply: KE1X_VOLTAGE_RANGE,
+        flash_controller: FlashController::Ftfe,
+        sector_size: 4096,
+        flash_config_address: 0x0000_0400,
+        fsec_offset: 0xC,
+        sdid_address: SIM_SDID_ADDRESS,
+        sdid_mask: 0xF0F0_0000,
+        sdid_value: 0x1010_0000,
+        fcfg1_address: Some(SIM_FCFG1_ADDRESS),
+        id_layout: &KINETIS_ID_LAYOUT,
+        uid_addresses: &KINETIS_UID,
+        naming: PartNaming::Kinetis { prefix: "KE", core: 'F' },
+    },
+    DeviceFamily {
+        name: "S32K1xx",
+        core: "Cortex-M4F",
+        mdm_ap_idr: MDM_IDR_CM4,
+        mdm_status_bits: MDM_STATUS_NO_LLS_VLLS,
+        mdm_control_bits: MDM_CONTROL_NO_VLLS,
+        flash: MemoryRegion { start: 0x0000_0000, end: 0x0020_0000 },
+        sram: MemoryRegion { start: 0x1FFE_0000, end: 0x2001_F000 },
+        supply: KE1X_VOLTAGE_RANGE,
+        flash_controller: FlashController::Ftfc,
+        sector_size: 4096,
+        flash_config_address: 0x0000_0400,
+        fsec_offset: 0xC,
+        sdid_address: SIM_SDID_ADDRESS,
+        sdid_mask: 0xF000_0000,
+        sdid_value: 0x1000_0000,
+        fcfg1_address: Some(SIM_FCFG1_ADDRESS),
+        id_layout: &S32K_ID_LAYOUT,
+        uid_addresses: &KINETIS_UID,
+        naming: PartNaming::S32k,
+    },
+    DeviceFamily {
+        name: "KE02",
+        core: "Cortex-M0+",
+        mdm_ap_idr: MDM_IDR_CM0P,
+        mdm_status_bits: MDM_STATUS_NO_VLP,
+        mdm_control_bits: MDM_CONTROL_NO_VLLS,
+        flash: MemoryRegion { start: 0x0000_0000, end: 0x0001_0000 },
+        sram: MemoryRegion { start: 0x1FFF_FC00, end: 0x2000_0C00 },
+        supply: KE1X_VOLTAGE_RANGE,
+        flash_controller: FlashController::Ftmrh,
+        sector_size: 512,
+        flash_config_address: KE0X_FLASH_CONFIG,
+        fsec_offset: 0xF,
+        sdid_address: KE0X_SRSID_ADDRESS,
+        sdid_mask: 0xFF00_0000,
+        sdid_value: 0x0200_0000,
+        fcfg1_address: None,
+        id_layout: &KE0X_ID_LAYOUT,
+        uid_addresses: &[],
+        naming: PartNaming::Kinetis { prefix: "KE", core: 'Z' },
+    },
+    DeviceFamily {
+        name: "KE04",
+        core: "Cortex-M0+",
+        mdm_ap_idr: MDM_IDR_CM0P,
+        mdm_status_bits: MDM_STATUS_NO_VLP,
+        mdm_control_bits: MDM_CONTROL_NO_VLLS,
+        flash: MemoryRegion { start: 0x0000_0000, end: 0x0002_0000 },
+        sram: MemoryRegion { start: 0x1FFF_F000, end: 0x2000_3000 },
+        supply: KE1X_VOLTAGE_RANGE,
+        flash_controller: FlashController::Ftmre,
+        sector_size: 512,
+        flash_config_address: KE0X_FLASH_CONFIG,
+        fsec_offset: 0xF,
+        sdid_address: KE0X_SRSID_ADDRESS,
+        sdid_mask: 0xFF00_0000,
+        sdid_value: 0x0400_0000,
+        fcfg1_address: None,
+        id_layout: &KE0X_ID_LAYOUT,
+        uid_addresses: &[],
+        naming: PartNaming::Kinetis { prefix: "KE", core: 'Z' },
+    },
+    DeviceFamily {
+        name: "KE06",
+        core: "Cortex-M0+",
+        mdm_ap_idr: MDM_IDR_CM0P,
+        mdm_status_bits: MDM_STATUS_NO_VLP,
+        mdm_control_bits: MDM_CONTROL_NO_VLLS,
+        flash: MemoryRegion { start: 0x0000_0000, end: 0x0002_0000 },
+        sram: MemoryRegion { start: 0x1FFF_F000, end: 0x2000_3000 },
+        supply: KE1X_VOLTAGE_RANGE,
+        flash_controller: FlashController::Ftmre,
+        sector_size: 512,
+        flash_config_address: KE0X_FLASH_CONFIG,
+        fsec_offset: 0xF,
+        sdid_address: KE0X_SRSID_ADDRESS,
+        sdid_mask: 0xFF00_0000,
+        sdid_value: 0x0600_0000,
+        fcfg1_address: None,
+        id_layout: &KE0X_ID_LAYOUT,
+        uid_addresses: &[],
+        naming: PartNaming::Kinetis { prefix: "KE", core: 'Z' },
+    },
+];
+
+/// `known_device_families` - names of `DEVICE_FAMILIES`, for messages
+pub fn known_device_families() -> String {
+    DEVICE_FAMILIES.iter().map(|family| family.name).collect::<Vec<_>>().join(", ")
+}
+
+/// `find_device_family` - family by name, case insensitive
+pub fn find_device_family(name: &str) -> Result<&'static DeviceFamily, Error> {
+    DEVICE_FAMILIES
+        .iter()
+        .find(|family| family.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| Error::UnknownDevice {
+            what: format!("family {}", name),
+            known: known_device_families(),
+        })
+}
+
+/// `supply_range` - supply range of the `named` family, without name the range covering all families
+pub fn supply_range(named: Option<&str>) -> Result<VoltageRange, Error> {
+    match named {
+        Some(name) => Ok(find_device_family(name)?.supply),
+        None => Ok(any_family_supply()),
+    }
+}
+
+/// `any_family_supply` - union of `DeviceFamily::supply`, for a target of unknown family
+pub fn any_family_supply() -> VoltageRange {
+    DEVICE_FAMILIES[1..].iter().fold(DEVICE_FAMILIES[0].supply, |range, family| range.union(&family.supply))
+}
+
+/// `mdm_control_bits` - MDM-AP Control bits of the `named` family, without name the bits
+/// implemented by every family with MDM-AP IDR `idr`
+pub fn mdm_control_bits(named: Option<&str>, idr: u32) -> Result<u32, Error> {
+    match named {
+        Some(name) => Ok(find_device_family(name)?.mdm_control_bits),
+        None => Ok(families_for_idr(idr).iter().fold(MDM_CONTROL_ALL_BITS, |bits, family| bits & family.mdm_control_bits)),
+    }
+}
+
+/// `families_for_idr` - families with MDM-AP IDR `idr`, in table order
+pub fn families_for_idr(idr: u32) -> Vec<&'static DeviceFamily> {
+    DEVICE_FAMILIES.iter().filter(|family| family.mdm_ap_idr == idr).collect()
+}
+
+/// `detect_device_family` - family by MDM-AP IDR, then by SDID match if the IDR is shared
+///
+/// SDID is read over `mem_ap`, so the core should be halted and the part unsecured.
+pub fn detect_device_family<T: Transport + ?Sized>(
+    iface: &mut T,
+    mem_ap: u8,
+    mdm_ap_idr: u32,
+) -> Result<&'static DeviceFamily, Error> {
+    let candidates = families_for_idr(mdm_ap_idr);
+    if let [family] = candidates.as_slice() {
+        return Ok(family);
+    }
+    for family in &candidates {
+        match iface.read_mem_word(mem_ap, family.sdid_address) {
+            Ok(sdid) if family.sdid_matches(sdid) => return Ok(family),
+            Ok(_) => {}
+            Err(err) => log::debug!("{} SDID not readable: {}", family.name, report(&err)),
+        }
+    }
+    Err(Error::UnknownDevice {
+        what: format!("MDM-AP IDR {:#010X}, no SDID match, select the family by name", mdm_ap_idr),
+        known: known_device_families(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_family_with_other_idr_is_rejected() {
+        let options = ConnectOptions { device: Some("KE1xF".to_string()), ..ConnectOptions::default() };
+        let result = connect_sim(SimTarget::new(SimScenario::Normal), &options);
+        assert!(
+            matches!(result, Err(Error::IdrMismatch { expected: MDM_IDR_CM4, found: IDR_REG_CHECK_VALUE })),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn ke02_srsid_has_no_flash_size() {
+        let ke02 = find_device_family("ke02").unwrap();
+        let device = DeviceInfo::parse(ke02, 0x0216_0082, 0, None);
+        assert_eq!(device.part_number(), "KE02Z");
+        assert_eq!(device.pin_count, Some(48));
+        assert_eq!(device.revision, Some(1));
+        assert_eq!((device.series_id, device.die_id, device.sram_size, device.flash_size), (None, None, None, None));
+        assert!(!device.to_string().contains('?'), "{}", device);
+    }
+
+    #[test]
+    fn s32k_sdid_naming() {
+        let s32k = find_device_family("S32K1xx").unwrap();
+        let device = DeviceInfo::parse(s32k, 0x144B_0400, 0x0B00_0000, None);
+        assert_eq!(device.part_number(), "S32K144");
+        assert_eq!(device.pin_count, Some(100));
+        assert_eq!((device.die_id, device.sram_size), (None, None));
+        assert_eq!(device.flash_size, Some(512 * 1024));
+        assert!(matches!(device.check_part("S32K142"), Err(Error::PartMismatch { .. })));
+    }
+
+    #[test]
+    fn control_bits_by_name_or_common_to_idr() {
+        assert_eq!(mdm_control_bits(Some("KL2x"), MDM_IDR_CM0P).unwrap(), MDM_CONTROL_ALL_BITS);
+        assert_eq!(mdm_control_bits(Some("KE1xZ"), MDM_IDR_CM0P).unwrap(), MDM_CONTROL_NO_VLLS);
+        /* KL2x shares the Cortex-M0+ IDR with KE1xZ / KE0x, VLLSx bits are not common */
+        assert_eq!(mdm_control_bits(None, MDM_IDR_CM0P).unwrap(), MDM_CONTROL_NO_VLLS);
+        assert!(matches!(mdm_control_bits(Some("K64"), MDM_IDR_CM4), Err(Error::UnknownDevice { .. })));
+    }
+}
@@ -0,0 +1,265 @@
+use super::*;
+
+use std::fmt;
+
+/// SW-DP registers, `DP_IDCODE` read / `DP_ABORT` write share address 0
+pub const DP_IDCODE: u8 = 0x0;
+pub const DP_ABORT: u8 = 0x0;
+pub const DP_CTRL_STAT: u8 = 0x4;
+
+/// `DP_CTRL_STAT` bits
+pub const DP_CTRL_STAT_STICKYORUN: u32 = 1 << 1;
+pub const DP_CTRL_STAT_STICKYCMP: u32 = 1 << 4;
+pub const DP_CTRL_STAT_STICKYERR: u32 = 1 << 5;
+pub const DP_CTRL_STAT_READOK: u32 = 1 << 6;
+pub const DP_CTRL_STAT_WDATAERR: u32 = 1 << 7;
+pub const DP_CTRL_STAT_CDBGPWRUPREQ: u32 = 1 << 28;
+pub const DP_CTRL_STAT_CDBGPWRUPACK: u32 = 1 << 29;
+pub const DP_CTRL_STAT_CSYSPWRUPREQ: u32 = 1 << 30;
+pub const DP_CTRL_STAT_CSYSPWRUPACK: u32 = 1 << 31;
+
+/// `DP_ABORT` bits
+pub const DP_ABORT_STKCMPCLR: u32 = 1 << 1;
+pub const DP_ABORT_STKERRCLR: u32 = 1 << 2;
+pub const DP_ABORT_WDERRCLR: u32 = 1 << 3;
+pub const DP_ABORT_ORUNERRCLR: u32 = 1 << 4;
+
+/// `DP_POWER_UP_TIMEOUT_MS` - max wait of CDBGPWRUPACK / CSYSPWRUPACK
+pub const DP_POWER_UP_TIMEOUT_MS: u64 = 100;
+
+/// `JEP106_ARM` - JEP106 designer code of ARM Ltd. (continuation 4, identity 0x3B)
+pub const JEP106_ARM: u16 = 0x23B;
+
+/// `DpIdcode` - decoded DP IDCODE (DPIDR)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DpIdcode {
+    pub value: u32,
+    /// JEP106 continuation code `[11:8]` and identity code `[7:1]`
+    pub designer: u16,
+    /// DP version `[15:12]`, 1 - DPv1, 2 - DPv2
+    pub dp_version: u8,
+    /// `MIN` - minimal debug port, no pushed operations
+    pub min: bool,
+    pub part_no: u8,
+    pub revision: u8,
+}
+
+impl DpIdcode {
+    pub fn parse_from_u32(value: u32) -> Self {
+        Self {
+            value,
+            designer: ((value >> 1) & 0x7FF) as u16,
+            dp_version: ((value >> 12) & 0xF) as u8,
+            min: value & (1 << 16) != 0,
+            part_no: ((value >> 20) & 0xFF) as u8,
+            revision: ((value >> 28) & 0xF) as u8,
+        }
+    }
+
+    pub fn designer_name(&self) -> &'static str {
+        match self.designer {
+            JEP106_ARM => "ARM",
+            _ => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for DpIdcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "IDCODE {:#010X} designer {:#05X} ({}), part {:#04X}, DPv{}, revision {}{}",
+            self.value,
+            self.designer,
+            self.designer_name(),
+            self.part_no,
+            self.dp_version,
+            self.revision,
+            if self.min { ", MINDP" } else { "" }
+        )
+    }
+}
+
+/// `DpCtrlStat` - decoded DP CTRL/STAT
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DpCtrlStat {
+    pub value: u32,
+    pub sticky_orun: bool,
+    pub sticky_cmp: bool,
+    pub sticky_err: bool,
+    pub read_ok: bool,
+    pub wdata_err: bool,
+    pub cdbg_pwrup_req: bool,
+    pub cdbg_pwrup_ack: bool,
+    pub csys_pwrup_req: bool,
+    pub csys_pwrup_ack: bool,
+}
+
+impl DpCtrlStat {
+    pub fn parse_from_u32(value: u32) -> Self {
+        Self {
+            value,
+            sticky_orun: value & DP_CTRL_STAT_STICKYORUN != 0,
+            sticky_cmp: value & DP_CTRL_STAT_STICKYCMP != 0,
+            sticky_err: value & DP_CTRL_STAT_STICKYERR != 0,
+            read_ok: value & DP_CTRL_STAT_READOK != 0,
+            wdata_err: value & DP_CTRL_STAT_WDATAERR != 0,
+            cdbg_pwrup_req: value & DP_CTRL_STAT_CDBGPWRUPREQ != 0,
+            cdbg_pwrup_ack: value & DP_CTRL_STAT_CDBGPWRUPACK != 0,
+            csys_pwrup_req: value & DP_CTRL_STAT_CSYSPWRUPREQ != 0,
+            csys_pwrup_ack: value & DP_CTRL_STAT_CSYSPWRUPACK != 0,
+        }
+    }
+
+    /// `powered_up` - debug and system power-up acknowledged
+    pub fn powered_up(&self) -> bool {
+        self.cdbg_pwrup_ack && self.csys_pwrup_ack
+    }
+
+    pub fn has_sticky_errors(&self) -> bool {
+        self.sticky_orun || self.sticky_cmp || self.sticky_err || self.wdata_err
+    }
+
+    /// `abort_clear_mask` - `DP_ABORT` value clearing the sticky flags which are set
+    pub fn abort_clear_mask(&self) -> u32 {
+        let mut abort = 0;
+        if self.sticky_orun {
+            abort |= DP_ABORT_ORUNERRCLR;
+        }
+        if self.sticky_cmp {
+            abort |= DP_ABORT_STKCMPCLR;
+        }
+        if self.sticky_err {
+            abort |= DP_ABORT_STKERRCLR;
+        }
+        if self.wdata_err {
+            abort |= DP_ABORT_WDERRCLR;
+        }
+        abort
+    }
+
+    pub fn fields(&self) -> [(&'static str, bool); 9] {
+        [
+            ("STICKYORUN", self.sticky_orun),
+            ("STICKYCMP", self.sticky_cmp),
+            ("STICKYERR", self.sticky_err),
+            ("READOK", self.read_ok),
+            ("WDATAERR", self.wdata_err),
+            ("CDBGPWRUPREQ", self.cdbg_pwrup_req),
+            ("CDBGPWRUPACK", self.cdbg_pwrup_ack),
+            ("CSYSPWRUPREQ", self.csys_pwrup_req),
+            ("CSYSPWRUPACK", self.csys_pwrup_ack),
+        ]
+    }
+}
+
+impl fmt::Display for DpCtrlStat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CTRL/STAT {:#010X}", self.value)?;
+        write_set_fields(f, &self.fields())
+    }
+}
+
+/// `DpHealth` - result of `dp_health_check`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DpHealth {
+    pub idcode: DpIdcode,
+    /// CTRL/STAT before sticky errors were cleared
+    pub ctrl_stat: DpCtrlStat,
+    /// sticky errors were found and cleared
+    pub sticky_cleared: bool,
+}
+
+pub fn read_idcode<T: Transport + ?Sized>(iface: &mut T) -> Result<DpIdcode, Error> {
+    Ok(DpIdcode::parse_from_u32(iface.read_dp_register(DP_IDCODE)?))
+}
+
+pub fn read_ctrl_stat<T: Transport + ?Sized>(iface: &mut T) -> Result<DpCtrlStat, Error> {
+    Ok(DpCtrlStat::parse_from_u32(iface.read_dp_register(DP_CTRL_STAT)?))
+}
+
+/// `clear_sticky_errors` - write `DP_ABORT` for the sticky flags set, return CTRL/STAT before clear
+pub fn clear_sticky_errors<T: Transport + ?Sized>(iface: &mut T) -> Result<DpCtrlStat, Error> {
+    let ctrl_stat = read_ctrl_stat(iface)?;
+    if ctrl_stat.has_sticky_errors() {
+        iface.write_dp_register(DP_ABORT, ctrl_stat.abort_clear_mask())?;
+    }
+    Ok(ctrl_stat)
+}
+
+/// `power_up` - request debug and system power-up, wait both acknowledges
+pub fn power_up<T: Transport + ?Sized>(iface: &mut T) -> Result<DpCtrlStat, Error> {
+    let ctrl_stat = read_ctrl_stat(iface)?;
+    if ctrl_stat.powered_up() {
+        return Ok(ctrl_stat);
+    }
+    iface.write_dp_register(DP_CTRL_STAT, DP_CTRL_STAT_CDBGPWRUPREQ | DP_CTRL_STAT_CSYSPWRUPREQ)?;
+
+    let start = time::Instant::now();
+    let timeout = time::Duration::from_millis(DP_POWER_UP_TIMEOUT_MS);
+    loop {
+        let ctrl_stat = read_ctrl_stat(iface)?;
+        if ctrl_stat.powered_up() {
+            return Ok(ctrl_stat);
+        }
+        if start.elapsed() >= timeout {
+            return Err(Error::DpPowerUpFailed { ctrl_stat: ctrl_stat.value });
+        }
+        thread::sleep(time::Duration::from_millis(1));
+    }
+}
+
+/// `dp_health_check` - read IDCODE, verify power-up handshake, clear sticky errors
+///
+/// Fails here mean wiring / probe / DP problems, not MDM-AP or core ones.
+pub fn dp_health_check<T: Transport + ?Sized>(iface: &mut T) -> Result<DpHealth, Error> {
+    let idcode = read_idcode(iface)?;
+    log::info!("DP {}", idcode);
+
+    power_up(iface)?;
+    let ctrl_stat = clear_sticky_errors(iface)?;
+    if ctrl_stat.has_sticky_errors() {
+        log::warn!("DP sticky errors cleared: {}", ctrl_stat);
+    } else {
+        log::debug!("DP {}", ctrl_stat);
+    }
+    Ok(DpHealth {
+        idcode,
+        ctrl_stat,
+        sticky_cleared: ctrl_stat.has_sticky_errors(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idcode_of_cortex_m0_plus_dp() {
+        let idcode = DpIdcode::parse_from_u32(0x0BC1_1477);
+        assert_eq!(idcode.designer, JEP106_ARM);
+        assert_eq!(idcode.designer_name(), "ARM");
+        assert_eq!((idcode.dp_version, idcode.part_no, idcode.revision), (1, 0xBC, 0));
+        assert!(idcode.min);
+        assert!(idcode.to_string().ends_with(", MINDP"), "{}", idcode);
+    }
+
+    #[test]
+    fn idcode_revision_and_dp_version() {
+        let dpv1 = DpIdcode::parse_from_u32(0x2BA0_1477);
+        assert!(!dpv1.min);
+        assert_eq!((dpv1.part_no, dpv1.revision), (0xBA, 2));
+
+        let dpv2 = DpIdcode::parse_from_u32(0x0BC1_2477);
+        assert_eq!(dpv2.dp_version, 2);
+        assert_ne!(DpIdcode::parse_from_u32(0x0BC1_1001).designer_name(), "ARM");
+    }
+
+    #[test]
+    fn ctrl_stat_abort_clears_only_set_sticky_flags() {
+        let ctrl_stat = DpCtrlStat::parse_from_u32(DP_CTRL_STAT_STICKYERR | DP_CTRL_STAT_CDBGPWRUPACK | DP_CTRL_STAT_CSYSPWRUPACK);
+        assert!(ctrl_stat.powered_up() && ctrl_stat.has_sticky_errors());
+        assert_eq!(ctrl_stat.abort_clear_mask(), DP_ABORT_STKERRCLR);
+        assert_eq!(DpCtrlStat::parse_from_u32(DP_CTRL_STAT_CDBGPWRUPACK).abort_clear_mask(), 0);
+    }
+}
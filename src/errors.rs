@@ -3,14 +3,114 @@
 
 use std::fmt;
 use std::io;
+use std::time::Duration;
 
 
+/// `BoxError` - underlying error of probe backend (probe-rs, simulator ...)
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
 
 #[allow(non_camel_case_types)]
-#[derive(Debug,Clone)]
+#[derive(Debug)]
 pub enum Error {
 
-   MdmExample(String),
+   /// no connected debug probe matches the selection
+   ProbeNotFound(String),
+
+   /// more connected debug probes match the selection
+   ProbeAmbiguous { selection: String, connected: String },
+
+   /// debug probe found, but can't be opened
+   ProbeOpen { source: BoxError },
+
+   /// probe operation failed (attach, reset, ARM interface init, flush ...)
+   Probe { operation: &'static str, source: BoxError },
+
+   /// direct USB access to the probe failed (ST-Link version read)
+   Usb { operation: &'static str, source: rusb::Error },
+
+   /// target reference voltage is about 0 V, target not powered
+   TargetUnpowered { millivolts: u32 },
+
+   /// target reference voltage outside the allowed range
+   TargetVoltageOutOfRange { millivolts: u32, min_mv: u32, max_mv: u32 },
+
+   /// SWD link failed at every clock of `SWD_SPEEDS_KHZ`, `source` is the failure at the slowest
+   SwdSpeedNegotiationFailed { slowest: u32, source: BoxError },
+
+   /// ST-Link firmware can't access AP other than AP 0, MDM-AP is not reachable
+   StLinkFirmwareTooOld { found: String, required: String },
+
+   /// read of DP register failed
+   DpRead { address: u8, source: BoxError },
+
+   /// write of DP register failed
+   DpWrite { address: u8, value: u32, source: BoxError },
+
+   /// debug / system power-up request not acknowledged in DP CTRL/STAT
+   DpPowerUpFailed { ctrl_stat: u32 },
+
+   /// read of raw AP register failed
+   ApRead { ap: u8, address: u8, source: BoxError },
+
+   /// write of raw AP register failed
+   ApWrite { ap: u8, address: u8, value: u32, source: BoxError },
+
+   /// read of memory word over MEM-AP failed
+   MemRead { ap: u8, address: u64, source: BoxError },
+
+   /// write of memory word over MEM-AP failed
+   MemWrite { ap: u8, address: u64, value: u32, source: BoxError },
+
+   /// condition not reached in time
+   Timeout { operation: &'static str, elapsed: Duration },
+
+   /// System Security is set, debug access is blocked until mass erase
+   TargetSecured,
+
+   /// MDM-AP IDR is not the expected one, probably not a Kinetis MDM-AP
+   IdrMismatch { expected: u32, found: u32 },
+
+   /// AP scan did not find the Kinetis MDM-AP / AHB MEM-AP pair, `aps` - what was found
+   UnexpectedApTopology { reason: &'static str, aps: String },
+
+   /// core not halted after DHCSR C_HALT write
+   DhcsrHaltFailed { dhcsr: u32 },
+
+   /// MDM-AP Debug Disable is set and can't be cleared, core can't be halted
+   DebugDisabled { control: u32 },
+
+   /// Debug Request set, Debug Disable clear, but the core did not halt
+   DebugRequestIgnored { status: u32, control: u32 },
+
+   /// MDM-AP Core Hold Reset set, but the core halted before the hold was released
+   CoreHoldNotEffective { status: u32, control: u32 },
+
+   /// core halted after reset with VC_CORERESET armed, but not at the reset vector
+   VectorCatchMissed { pc: u32, reset_vector: u32 },
+
+   /// core not caught before it entered low power mode, in all attempts
+   LowPowerRecoveryFailed { attempts: u32, observed: String },
+
+   /// connected part is not the one the image is built for
+   PartMismatch { expected: String, found: String },
+
+   /// device family not in `DEVICE_FAMILIES` / not detected
+   UnknownDevice { what: String, known: String },
+
+   /// MDM-AP Control readback differs from written value after all attempts
+   ControlBitNotLatched { expected: u32, readback: u32, attempts: u32 },
+
+   /// Mass erase by MDM-AP is disabled by FSEC[MEEN]
+   MassEraseDisabled,
+
+   /// operation is not supported by the probe / transport
+   Unsupported(&'static str),
+
+   /// command line option `name` has a value which can't be used
+   InvalidArgument { name: &'static str, value: String },
+
+   /// output file / terminal write failed
+   Io(io::Error),
 }
 
 
@@ -18,6 +118,157 @@ pub enum Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-       write!(f, "{:?}", self)
+        match self {
+            Error::ProbeNotFound(what) => write!(f, "debug probe not found: {}", what),
+            Error::ProbeAmbiguous { selection, connected } => write!(
+                f,
+                "more debug probes match {}, select by --probe-serial, connected: {}",
+                selection, connected
+            ),
+            Error::ProbeOpen { .. } => write!(f, "debug probe found, but can't be opened"),
+            Error::Probe { operation, .. } => write!(f, "probe operation failed: {}", operation),
+            Error::Usb { operation, .. } => write!(f, "USB operation failed: {}", operation),
+            Error::TargetUnpowered { millivolts } => write!(
+                f,
+                "target not powered: reference voltage {:.2} V, power the target before connecting",
+                *millivolts as f32 / 1000.0
+            ),
+            Error::TargetVoltageOutOfRange { millivolts, min_mv, max_mv } => write!(
+                f,
+                "target voltage {:.2} V outside {:.2}-{:.2} V",
+                *millivolts as f32 / 1000.0,
+                *min_mv as f32 / 1000.0,
+                *max_mv as f32 / 1000.0
+            ),
+            Error::SwdSpeedNegotiationFailed { slowest, .. } => {
+                write!(f, "SWD link not stable down to {} kHz, check wiring and target power", slowest)
+            }
+            Error::StLinkFirmwareTooOld { found, required } => write!(
+                f,
+                "ST-Link firmware {} can't access APs other than AP 0 (MDM-AP), upgrade to {} or newer by ST-LinkUpgrade (STSW-LINK007)",
+                found, required
+            ),
+            Error::DpRead { address, .. } => write!(f, "failed read DP register {:#04X}", address),
+            Error::DpWrite { address, value, .. } => write!(
+                f,
+                "failed write {:#010X} to DP register {:#04X}",
+                value, address
+            ),
+            Error::DpPowerUpFailed { ctrl_stat } => write!(
+                f,
+                "DP power-up not acknowledged, CTRL/STAT {:#010X}",
+                ctrl_stat
+            ),
+            Error::ApRead { ap, address, .. } => {
+                write!(f, "failed read AP {} register {:#04X}", ap, address)
+            }
+            Error::ApWrite { ap, address, value, .. } => write!(
+                f,
+                "failed write {:#010X} to AP {} register {:#04X}",
+                value, ap, address
+            ),
+            Error::MemRead { ap, address, .. } => {
+                write!(f, "failed read word {:#010X} over AP {}", address, ap)
+            }
+            Error::MemWrite { ap, address, value, .. } => write!(
+                f,
+                "failed write {:#010X} to word {:#010X} over AP {}",
+                value, address, ap
+            ),
+            Error::Timeout { operation, elapsed } => write!(
+                f,
+                "timeout: {} not done after {}ms",
+                operation,
+                elapsed.as_millis()
+            ),
+            Error::TargetSecured => write!(f, "target is secured, mass erase is required to unsecure"),
+            Error::IdrMismatch { expected, found } => write!(
+                f,
+                "MDM-AP IDR mismatch: expected {:#010X}, found {:#010X}",
+                expected, found
+            ),
+            Error::UnexpectedApTopology { reason, aps } => {
+                write!(f, "unexpected AP topology, {}: {}", reason, aps)
+            }
+            Error::DhcsrHaltFailed { dhcsr } => {
+                write!(f, "core not halted, DHCSR {:#010X}", dhcsr)
+            }
+            Error::DebugDisabled { control } => write!(
+                f,
+                "debug disabled by MDM-AP DBG_DIS, bit can't be cleared, MDM_CONTROL {:#010X}",
+                control
+            ),
+            Error::DebugRequestIgnored { status, control } => write!(
+                f,
+                "core ignored debug request, MDM_STATUS {:#010X}, MDM_CONTROL {:#010X}",
+                status, control
+            ),
+            Error::CoreHoldNotEffective { status, control } => write!(
+                f,
+                "core left core hold reset before release, MDM_STATUS {:#010X}, MDM_CONTROL {:#010X}",
+                status, control
+            ),
+            Error::VectorCatchMissed { pc, reset_vector } => write!(
+                f,
+                "core halted at PC {:#010X}, not at reset vector {:#010X}",
+                pc, reset_vector
+            ),
+            Error::LowPowerRecoveryFailed { attempts, observed } => write!(
+                f,
+                "core not caught before low power mode in {} attempts, observed: {}",
+                attempts, observed
+            ),
+            Error::PartMismatch { expected, found } => {
+                write!(f, "part mismatch: expected {}, connected {}", expected, found)
+            }
+            Error::UnknownDevice { what, known } => {
+                write!(f, "unknown device: {}, known families: {}", what, known)
+            }
+            Error::ControlBitNotLatched { expected, readback, attempts } => write!(
+                f,
+                "MDM-AP Control not latched after {} attempts: written {:#010X}, read back {:#010X}",
+                attempts, expected, readback
+            ),
+            Error::MassEraseDisabled => write!(f, "mass erase is disabled by FSEC[MEEN]"),
+            Error::Unsupported(what) => write!(f, "not supported by probe: {}", what),
+            Error::InvalidArgument { name, value } => write!(f, "invalid {} value {}", name, value),
+            Error::Io(_) => write!(f, "output failed"),
+        }
     }
-}
\ No newline at end of file
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ProbeOpen { source }
+            | Error::Probe { source, .. }
+            | Error::SwdSpeedNegotiationFailed { source, .. }
+            | Error::DpRead { source, .. }
+            | Error::DpWrite { source, .. }
+            | Error::ApRead { source, .. }
+            | Error::ApWrite { source, .. }
+            | Error::MemRead { source, .. }
+            | Error::MemWrite { source, .. } => Some(source.as_ref()),
+            Error::Usb { source, .. } => Some(source),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// `report` - error message with the whole `source()` chain, for user output
+pub fn report(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
@@ -2,134 +2,350 @@
 
 
 mod mdm_ap;
+mod transport;
+mod sim;
+mod watch;
+mod connect;
+mod core_debug;
+mod dp;
+mod ap_scan;
+mod device_info;
+mod devices;
+mod probe_select;
+mod stlink_usb;
+mod probe_inventory;
+mod swd_speed;
+mod target_voltage;
 pub mod errors;
 
 use mdm_ap::*;
+use transport::*;
+use sim::*;
+use watch::*;
+use connect::*;
+use core_debug::*;
+use dp::*;
+use ap_scan::*;
+use device_info::*;
+use devices::*;
+use probe_select::*;
+use stlink_usb::*;
+use probe_inventory::*;
+use swd_speed::*;
+use target_voltage::*;
 pub use errors::*;
 
 use std::{thread, time};
 use std::ops::DerefMut;
 
 use probe_rs::{
+    DebugProbeError,
     DebugProbeType,
     architecture::arm::{ApAddress, ap::MemoryAp, 
         ArmProbeInterface,
         armv6m::Dhcsr,
         DpAddress},
-    DebugProbeInfo,
     MemoryMappedRegister,
     Probe};
 
 
 pub fn stlink() -> Result<Probe, Error> {
-    
-    let list = Probe::list_all();
-    let device =
-    list.iter()
-    .filter(|prog: &&DebugProbeInfo |if prog.probe_type == DebugProbeType::StLink { true } else { false } )
-    .next()
-    .ok_or(Error::MdmExample("StLink not connected - not found by VID & PID".to_string()));
-    
-    let stlink = device?.open().map_err(|err | Error::MdmExample(format!("StLink Can't Be Open: error {:?}, ",  err )))?;
-    Ok(stlink)
+    open_probe(&ProbeSelector { probe_type: Some(DebugProbeType::StLink), ..ProbeSelector::default() })
 }
 
-pub fn debug_mode_on_an4835(mut probe :  Probe) -> Result<(), Error> {
-    
-    probe.attach_to_unspecified().map_err(|err | Error::MdmExample(format!("Failed base init Programmer. If this happend error after-write erase re-load programmer : error {:?}, ",  err )))?;
-    probe.target_reset().map_err(|err | Error::MdmExample(format!("Failed reset target : error {:?}, ",  err)))?;
+/// `open_transport` - check target `voltage`, attach probe to target, reset it as `strategy` needs, open ARM interface
+pub fn open_transport(mut probe :  Probe, strategy: ConnectStrategy, voltage: Option<&VoltageRange>) -> Result<ProbeRsTransport<'static>, Error> {
 
-    let mut iface = probe
-       .try_into_arm_interface().map_err(|err | Error::MdmExample(format!("Programmer failed open ARM Interface : error {:?}, ",  err )))?
-       .initialize_unspecified()
-       .map_err(|_ | Error::MdmExample("Programmer failed init ARM interface".to_string()))?;
+    if let Some(range) = voltage {
+        check_target_voltage(&mut probe, range)?;
+    }
 
+    let hardware_reset = strategy == ConnectStrategy::HardwareReset;
+    if hardware_reset {
+        /* NRST held low during attach, `hardware_reset_connect` release it - the probe must be able to */
+        probe.target_reset_deassert().map_err(|err| match err {
+            DebugProbeError::NotImplemented(_) | DebugProbeError::CommandNotSupportedByProbe(_) => {
+                Error::Unsupported("NRST release (target_reset_deassert)")
+            }
+            err => Error::Probe { operation: "deassert NRST", source: err.into() },
+        })?;
+        probe.target_reset_assert().map_err(|err | Error::Probe { operation: "assert NRST", source: err.into() })?;
+    }
+    if let Err(err) = probe.attach_to_unspecified() {
+        if hardware_reset {
+            let _ = probe.target_reset_deassert();
+        }
+        return Err(Error::Probe { operation: "attach (base init Programmer, if happend after-write erase re-load programmer)", source: err.into() });
+    }
+    if matches!(strategy, ConnectStrategy::An4835MdmReset | ConnectStrategy::CoreHoldRelease | ConnectStrategy::WakeAndCatch) {
+        probe.target_reset().map_err(|err | Error::Probe { operation: "reset target", source: err.into() })?;
+    }
 
-    println!("-----------------------------------------------------"); 
-    println!("MKE GENERAL INTERFACE : debug_mode_on based on AN4835");
-    println!("-----------------------------------------------------"); 
+    /* on failure get the probe back from the error, NRST must not stay asserted */
+    let release_nrst = |mut probe: Probe| {
+        if hardware_reset {
+            let _ = probe.target_reset_deassert();
+        }
+    };
+    let uninitialized = match probe.try_into_arm_interface() {
+        Ok(uninitialized) => uninitialized,
+        Err((probe, err)) => {
+            release_nrst(probe);
+            return Err(Error::Probe { operation: "open ARM interface", source: err.into() });
+        }
+    };
+    match uninitialized.initialize_unspecified() {
+        Ok(iface) => Ok(ProbeRsTransport::new(iface)),
+        Err((uninitialized, err)) => {
+            release_nrst(uninitialized.close());
+            Err(Error::Probe { operation: "init ARM interface", source: err.into() })
+        }
+    }
+}
 
-    /*  "SWD connection steps" based on AN4835  */
-    /* 1. init mdm ap reg, read current state */
-    let mut mdm_ap = MdmAP::read_mdm_ap_register(iface.deref_mut(), false)?;
-        
-    /* 2. write the System Reset Request bit. Keep reset low and establish communication with the ARM DAP.  */
-    mdm_ap.mdm_ap_reset_keep(iface.deref_mut())?;                
-
-    /* 3. The MDM-AP ID register can be read to verify that the connection is working correctly. */                                                                             
-    let idr_reg = mdm_ap.read_mdm_ap_idr(iface.deref_mut())?;   
-    if idr_reg != IDR_REG_CHECK_VALUE {           
-        println!( "IDR_reg != const value 0x001C_0020" );                             
-    } else {
-        println!( "MKE ID Register {}", &format!("{:#06X} OK ", idr_reg ));
-    }
-
-    /* 4. The MDM-AP ID register can be read to verify that the connection is working correctly. */                                                                            
-    mdm_ap.is_mdm_flash_ready(iface.deref_mut())?;
-
-    /* 5. Read the System Security bit to determine if security is enabled. If System Security = 0, then proceed. */                                                                             // 
-    mdm_ap.refresh_mdm_ap(iface.deref_mut(), false)?;
-    if mdm_ap.status.security == true {  
-        /* didn't try to halt core, just return Ok, so user can try mass erase chip if decide */
-        println!("Target connected, but secured");
-        println!("Target is secured, for unsecure mass erase");
-        panic!();
-    }
-
-    /* 6. Write the MDM-AP register to set the Debug Request bit */
-    mdm_ap.write_mdm_ap_control_bit(iface.deref_mut(), MKE_MDM_CONTROL_DBG_REQ_BIT)?;
-    //mdm_ap.write_mdm_ap_control_bit(iface.deref_mut(), MKE_MDM_CONTROL_CORE_HOLD_BIT)?;
-    mdm_ap.refresh_and_compare_mdm_ap(iface.deref_mut()," DBG_REQ_BIT set ".to_string())?;
-
-    /* Try to write HALT bit on DHCSR reg ARM core */
-    // When the steps above have been completed, debugging or flash programming can be started.
-    let mut dhcsr = Dhcsr(0);
-    dhcsr.set_c_halt(true);
-    dhcsr.set_c_debugen(true);
-    dhcsr.enable_write();
-
-    let mut probe = iface.memory_interface(MKE_DEFAULT_MEM_AP).map_err(|err | Error::MdmExample(format!("Failed get ARM interface : error {:?}, ",  err)))?;
-    let dhcsr_before: u32=   probe.read_word_32(Dhcsr::get_mmio_address()).map_err(|err | Error::MdmExample(format!("Failed read DHCSR ARM reg  : error {:?}, ",  err)))?;
-    probe.write_word_32(Dhcsr::get_mmio_address(), dhcsr.into()).map_err(|err | Error::MdmExample(format!("Probe Flush : error {:?}, ",  err)))?;
-    probe.flush().map_err(|err | Error::MdmExample(format!("Probe Flush : error {:?}, ",  err)))?;
-
-    thread::sleep(time::Duration::from_millis(500));
-    let dhcsr_after = probe.read_word_32(Dhcsr::get_mmio_address()).map_err(|err | Error::MdmExample(format!("Failed check after write DHCSR ARM reg  : error {:?}, ",  err)))?;
-    println!("Dhcsr_before write  {:04X}, Dhcsr_after write  {:04X}", &dhcsr_before, &dhcsr_after );
-    drop(probe);
-    mdm_ap.refresh_mdm_ap(iface.deref_mut(), true)?;
-
-    /*  7. clear the System Reset Request bit in the MDM-AP control register. */
-    mdm_ap.mdm_ap_clear_reset_bit(iface.deref_mut()).map_err(|err | Error::MdmExample(format!("Failed mdm_ap_clear_reset_bit  : error {:?}, ",  err)))?;   
-    thread::sleep(time::Duration::from_millis(50));
-    mdm_ap.refresh_mdm_ap(iface.deref_mut(), true)?;
-    
-    let mut probe = iface.memory_interface(MKE_DEFAULT_MEM_AP).map_err(|err | Error::MdmExample(format!("Failed get ARM interface : error {:?}, ",  err)))?;
-    let dhcsr_end=   probe.read_word_32(Dhcsr::get_mmio_address()).map_err(|err | Error::MdmExample(format!("Failed read DHCSR ARM reg  : error {:?}, ",  err)))?;
-    println!("dhcsr_end   {:04X}", &dhcsr_end );
-    drop(probe);
+pub fn debug_mode_on(mut probe :  Probe, options: &ConnectOptions) -> Result<Connection, Error> {
 
-    mdm_ap.refresh_mdm_ap(iface.deref_mut(), true)?;
+    let millivolts = match &options.target_voltage {
+        Some(range) => check_target_voltage(&mut probe, range)?,
+        None => None,
+    };
+    let mut transport = RetryTransport::new(open_transport(probe, options.strategy, None)?, AP_ACCESS_RETRIES);
+    let result = dp_health_check(&mut transport).and_then(|_| connect(&mut transport, options));
+    if result.is_err() && options.strategy == ConnectStrategy::HardwareReset {
+        /* failed before `hardware_reset_connect` released NRST, don't leave the target in reset */
+        let _ = transport.set_nrst(false);
+    }
+    let connection = result?;
+
+    /* the family was not known at the check before attach, its supply range may be narrower */
+    if let (Some(millivolts), true) = (millivolts, options.voltage_from_family) {
+        let supply = connection.family.supply;
+        if !supply.contains(millivolts) {
+            return Err(Error::TargetVoltageOutOfRange { millivolts, min_mv: supply.min_mv, max_mv: supply.max_mv });
+        }
+    }
+    Ok(connection)
+}
+
+/// `watch_mode` - live watch of MDM-AP, options:
+/// `--watch-interval-ms N`, `--watch-dhcsr`, `--watch-duration-s N`, `--watch-output FILE`
+pub fn watch_mode<T: Transport + ?Sized>(iface: &mut T, args: &[String]) -> Result<u64, Error> {
+
+    let mut config = WatchConfig::default();
+    if let Some(value) = arg_value(args, "--watch-interval-ms") {
+        /* 0 would poll the probe in a busy loop */
+        match value.parse() {
+            Ok(interval) if interval > 0 => config.interval = time::Duration::from_millis(interval),
+            _ => return Err(Error::InvalidArgument { name: "--watch-interval-ms", value: value.to_string() }),
+        }
+    }
+    if let Some(duration) = arg_value(args, "--watch-duration-s").and_then(|value| value.parse().ok()) {
+        config.duration = Some(time::Duration::from_secs(duration));
+    }
+    config.dhcsr = args.iter().any(|arg| arg == "--watch-dhcsr");
+
+    match arg_value(args, "--watch-output") {
+        Some(path) => {
+            let mut file = std::fs::File::create(path)?;
+            watch(iface, &config, &mut file)
+        }
+        None => watch(iface, &config, &mut std::io::stdout()),
+    }
+}
+
+/// `debug_disable_mode` - only set / clear MDM-AP Debug Disable, for tests of debug-disabled behaviour,
+/// `device` - family name, control bits common to the families with the found MDM-AP IDR if `None`
+pub fn debug_disable_mode<T: Transport + ?Sized>(iface: &mut T, device: Option<&str>, disable: bool) -> Result<MdmAP, Error> {
+
+    let ports = scan_aps(iface)?.ports()?;
+    let mut mdm_ap = MdmAP::read_mdm_ap_register_at(iface, ports.mdm_ap, false)?;
+    mdm_ap.control_bits = mdm_control_bits(device, ports.mdm_ap_idr)?;
+    mdm_ap.set_debug_disable(iface, disable)?;
+    mdm_ap.refresh_mdm_ap(iface, false)
+}
 
-    Ok(())
- 
+/// `arg_value` - value following `name` in command line
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let position = args.iter().position(|arg| arg == name)?;
+    args.get(position + 1).map(String::as_str)
 }
+
 pub fn main() {
 
+    /* diagnostic output goes through `log`, RUST_LOG=debug shows MDM-AP snapshots and diffs */
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let mass_erase = args.iter().any(|arg| arg == "--mass-erase");
+    let watch = args.iter().any(|arg| arg == "--watch");
+
+    /* --strategy <name> : how the target is reset while connecting, AN4835 by default */
+    let strategy_name = arg_value(&args, "--strategy").unwrap_or(ConnectStrategy::default().name());
+    let strategy = match ConnectStrategy::from_name(strategy_name) {
+        Some(strategy) => strategy,
+        None => {
+            println!("Unknown strategy {}, known:", strategy_name);
+            ConnectStrategy::ALL.iter().for_each(|strategy| println!("  {}", strategy.name()));
+            return;
+        }
+    };
+    /* --vector-catch : halt at reset vector by DEMCR VC_CORERESET, report PC and SP */
+    let vector_catch = args.iter().any(|arg| arg == "--vector-catch");
+    let mut options = ConnectOptions { strategy, mass_erase, vector_catch, ..ConnectOptions::default() };
+    /* --recovery-attempts N : attempt budget of wake-and-catch */
+    if let Some(attempts) = arg_value(&args, "--recovery-attempts").and_then(|value| value.parse().ok()) {
+        options.recovery_attempts = attempts;
+    }
+    /* --expect-part KE14Z256 : fail if another part is connected */
+    options.expected_part = arg_value(&args, "--expect-part").map(str::to_string);
+    /* --device <family> : skip family detection, e.g. KE1xZ, KL2x, S32K1xx */
+    options.device = arg_value(&args, "--device").map(str::to_string);
+    if let Ok(range) = supply_range(options.device.as_deref()) {
+        options.target_voltage = Some(range);
+    }
+    /* --target-voltage MIN-MAX : allowed target voltage in V, supply range of the family by default,
+    --no-voltage-check skips it */
+    if let Some(value) = arg_value(&args, "--target-voltage") {
+        match VoltageRange::from_arg(value) {
+            Some(range) => {
+                options.target_voltage = Some(range);
+                options.voltage_from_family = false;
+            }
+            None => {
+                println!("--target-voltage needs MIN-MAX in volts, e.g. 1.71-3.6");
+                return;
+            }
+        }
+    }
+    if args.iter().any(|arg| arg == "--no-voltage-check") {
+        options.target_voltage = None;
+    }
+
+    /* --debug-disable set|clear : write MDM-AP DBG_DIS only, no connect sequence */
+    let debug_disable = match arg_value(&args, "--debug-disable") {
+        None if args.iter().any(|arg| arg == "--debug-disable") => {
+            println!("--debug-disable needs set or clear");
+            return;
+        }
+        None => None,
+        Some("set") => Some(true),
+        Some("clear") => Some(false),
+        Some(value) => {
+            println!("Unknown --debug-disable value {}, known: set, clear", value);
+            return;
+        }
+    };
 
-    let probe_try_stlink = stlink();
+    /* --simulate <scenario> : run connect sequence on software model, no probe needed */
+    if args.iter().any(|arg| arg == "--simulate") {
+        let name = arg_value(&args, "--simulate").unwrap_or("normal");
+        let scenario = match SimScenario::from_name(name) {
+            Some(scenario) => scenario,
+            None => {
+                println!("Unknown scenario {}, known:", name);
+                SimScenario::ALL.iter().for_each(|scenario| println!("  {}", scenario.name()));
+                return;
+            }
+        };
+        let mut sim = SimTarget::new(scenario);
+        if let Some(disable) = debug_disable {
+            match debug_disable_mode(&mut sim, options.device.as_deref(), disable) {
+                Ok(mdm_ap) => log::info!("simulate {} : {}", name, mdm_ap),
+                Err(err) => log::error!("simulate {} : debug disable error: {}", name, report(&err)),
+            }
+            return;
+        }
+        if watch {
+            if let Err(err) = watch_mode(&mut sim, &args) {
+                log::error!("simulate {} : watch error: {}", name, report(&err));
+            }
+            return;
+        }
+        match connect_sim(sim, &options) {
+            Ok(connection) => log::info!("simulate {} : connected, {}", name, connection),
+            Err(err) => log::error!("simulate {} : connect error: {}", name, report(&err)),
+        }
+        return;
+    }
 
-    let probe = match probe_try_stlink {
+    /* --list-probes [--json] : connected probes with firmware version and in use flag */
+    if args.iter().any(|arg| arg == "--list-probes") {
+        let json = args.iter().any(|arg| arg == "--json");
+        if let Err(err) = write_inventory(&probe_inventory(), json, &mut std::io::stdout()) {
+            log::error!("list probes error: {}", report(&err));
+        }
+        return;
+    }
+
+    /* --probe <type>, --probe-vid-pid VID:PID, --probe-serial SN : which probe, the only ST-Link by default */
+    let mut selector = ProbeSelector::default();
+    if let Some(name) = arg_value(&args, "--probe") {
+        match probe_type_from_name(name) {
+            Some(probe_type) => selector.probe_type = Some(probe_type),
+            None => {
+                println!("Unknown probe type {}, known:", name);
+                PROBE_TYPES.iter().for_each(|probe_type| println!("  {}", probe_type_name(probe_type)));
+                return;
+            }
+        }
+    }
+    if let Some(value) = arg_value(&args, "--probe-vid-pid") {
+        match ProbeSelector::parse_vid_pid(value) {
+            Some(vid_pid) => selector.vid_pid = Some(vid_pid),
+            None => {
+                println!("--probe-vid-pid needs hex VID:PID, e.g. 0483:374B");
+                return;
+            }
+        }
+    }
+    selector.serial = arg_value(&args, "--probe-serial").map(str::to_string);
+    /* --skip-firmware-check : open an ST-Link even if its firmware version can't be read over USB */
+    selector.skip_firmware_check = args.iter().any(|arg| arg == "--skip-firmware-check");
+
+    /* --swd-speed <kHz|auto> : SWD clock, auto steps down from 4 MHz until MDM-AP IDR reads are stable */
+    let speed = match arg_value(&args, "--swd-speed") {
+        None => None,
+        Some(value) => match SwdSpeed::from_arg(value) {
+            Some(speed) => Some(speed),
+            None => {
+                println!("--swd-speed needs auto or clock in kHz, e.g. 1800");
+                return;
+            }
+        },
+    };
+
+    let probe_try_open = open_probe_with_speed(&selector, speed, options.target_voltage.as_ref());
+
+    let probe = match probe_try_open {
     
         Ok(probe) => { probe }
         Err(err) => { 
-            println!("StLink connection error: {:?}", err);
-            panic!();
+            log::error!("probe connection error: {}", report(&err));
+            std::process::exit(1);
         }
     };
 
-    if let Err(err) = debug_mode_on_an4835(probe) {
-        println!("debug_mode_on_an4835 error: {:?}", err);
+    if let Some(disable) = debug_disable {
+        let result = open_transport(probe, ConnectStrategy::HotAttach, options.target_voltage.as_ref()).and_then(|mut transport| debug_disable_mode(&mut transport, options.device.as_deref(), disable));
+        match result {
+            Ok(mdm_ap) => log::info!("{}", mdm_ap),
+            Err(err) => log::error!("debug disable error: {}", report(&err)),
+        }
+        return;
+    }
+
+    /* --watch : observe MDM-AP without reset and connect sequence */
+    if watch {
+        let result = open_transport(probe, ConnectStrategy::HotAttach, options.target_voltage.as_ref()).and_then(|mut transport| watch_mode(&mut transport, &args));
+        if let Err(err) = result {
+            log::error!("watch error: {}", report(&err));
+        }
+        return;
+    }
+
+    match debug_mode_on(probe, &options) {
+        Ok(connection) => log::info!("connected, {}", connection),
+        Err(err) => log::error!("debug_mode_on {} error: {}", strategy.name(), report(&err)),
     }
 
     
@@ -1,16 +1,13 @@
 use super::*;
 
-/// `MKE_MDM_AP_PORT` - AccessPort to `miscellaneous debug module (MDM)`
-pub const MKE_DEFAULT_MEM_AP: MemoryAp = MemoryAp::new(ApAddress {
-    dp: DpAddress::Default,
-    ap: 0,
-});
-
-/// `MKE_MDM_AP_PORT` - AccessPort to `miscellaneous debug module (MDM)`
-pub const MKE_MDM_AP_PORT: ApAddress = ApAddress {
-    dp: DpAddress::Default,
-    ap: 1,
-};
+use std::fmt;
+
+/// `MKE_DEFAULT_MEM_AP` - AccessPort index of AHB `MEM-AP` (system memory, core debug registers),
+/// used when AP scan is not done
+pub const MKE_DEFAULT_MEM_AP: u8 = 0;
+
+/// `MKE_MDM_AP_PORT` - AccessPort index of `miscellaneous debug module (MDM)`, used when AP scan is not done
+pub const MKE_MDM_AP_PORT: u8 = 1;
 
 /// `MKE_MDM_STATUS`
 /// `miscellaneous debug module (MDM)` is implemented on this device, which contains
@@ -34,6 +31,15 @@ pub const MKE_MDM_STATUS_FLASH_MASS_ERASE_ACK_BIT: u32 = 0b00000001;
 pub const MKE_MDM_STATUS_FLASH_READY_BIT: u32 = 0b00000010;
 pub const MKE_MDM_STATUS_SYSTEM_SECURITY_BIT: u32 = 0b00000100;
 pub const MKE_MDM_STATUS_SYSTEM_RESET_BIT: u32 = 0b00001000;
+pub const MKE_MDM_STATUS_MASS_ERASE_ENABLE_BIT: u32 = 0b00100000;
+pub const MKE_MDM_STATUS_BACKDOOR_KEY_ENABLE_BIT: u32 = 0b01000000;
+pub const MKE_MDM_STATUS_LP_ENABLED_BIT: u32 = 0b10000000;
+pub const MKE_MDM_STATUS_VLP_MODE_BIT: u32 = 0x0000_0100;
+pub const MKE_MDM_STATUS_LLS_EXIT_BIT: u32 = 0x0000_0200;
+pub const MKE_MDM_STATUS_VLLS_EXIT_BIT: u32 = 0x0000_0400;
+pub const MKE_MDM_STATUS_CORE_HALTED_BIT: u32 = 0x0001_0000;
+pub const MKE_MDM_STATUS_CORE_SLEEPDEEP_BIT: u32 = 0x0002_0000;
+pub const MKE_MDM_STATUS_CORE_SLEEPING_BIT: u32 = 0x0004_0000;
 
 /// `MKE_MDM_CONTROL`
 /// `miscellaneous debug module (MDM)` is implemented on this device, which contains
@@ -59,6 +65,31 @@ pub const MKE_MDM_CONTROL_DBG_REQ_BIT: u32 = 0b00000100;
 pub const MKE_MDM_CONTROL_SYS_RESET_BIT: u32 = 0b00001000;
 pub const MKE_MDM_CONTROL_CORE_HOLD_BIT: u32 = 0b00010000;
 
+/// `MDM_CONTROL_WRITE_RETRIES` - default attempts for verified read-modify-write of MDM-AP Control
+pub const MDM_CONTROL_WRITE_RETRIES: u32 = 3;
+
+/// `MdmApControlBit` - single request bit of MDM-AP Control register
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MdmApControlBit {
+    MassErase,
+    DebugDisable,
+    DebugRequest,
+    SysResetRequest,
+    CoreHold,
+}
+
+impl MdmApControlBit {
+    pub fn mask(&self) -> u32 {
+        match self {
+            MdmApControlBit::MassErase => MKE_MDM_CONTROL_FLASH_MASS_ERASE_BIT,
+            MdmApControlBit::DebugDisable => MKE_MDM_CONTROL_DBG_DIS_BIT,
+            MdmApControlBit::DebugRequest => MKE_MDM_CONTROL_DBG_REQ_BIT,
+            MdmApControlBit::SysResetRequest => MKE_MDM_CONTROL_SYS_RESET_BIT,
+            MdmApControlBit::CoreHold => MKE_MDM_CONTROL_CORE_HOLD_BIT,
+        }
+    }
+}
+
 ///`MKE_MDM_IDR_REG` = IDR register always reads `0x001C_0020`
 ///
 /// `miscellaneous debug module (MDM)` is implemented on this device, which contains
@@ -73,6 +104,55 @@ pub const MKE_MDM_IDR_REG: u8 = 0xFC;
 
 pub const IDR_REG_CHECK_VALUE: u32 = 0x001C_0020;
 
+/// `RESET_TIMEOUT_MS` - time for System Reset status to go low after reset request
+pub const RESET_TIMEOUT_MS: u64 = 20;
+/// `FLASH_READY_TIMEOUT_MS` - time for Flash Ready after reset
+pub const FLASH_READY_TIMEOUT_MS: u64 = 100;
+/// `MASS_ERASE_TIMEOUT_MS` - time for mass erase to be acked and to complete (control bit self-clear)
+pub const MASS_ERASE_TIMEOUT_MS: u64 = 10_000;
+/// `HALT_TIMEOUT_MS` - time for core to report halted after debug request
+pub const HALT_TIMEOUT_MS: u64 = 100;
+/// `POLL_INTERVAL_MS` - pause between two reads of MDM-AP while waiting
+pub const POLL_INTERVAL_MS: u64 = 1;
+
+/// `MdmTimeouts` - deadlines of MDM-AP waits, per operation
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MdmTimeouts {
+    pub reset: time::Duration,
+    pub flash_ready: time::Duration,
+    pub mass_erase: time::Duration,
+    pub halt: time::Duration,
+    pub poll_interval: time::Duration,
+}
+
+impl Default for MdmTimeouts {
+    fn default() -> Self {
+        Self {
+            reset: time::Duration::from_millis(RESET_TIMEOUT_MS),
+            flash_ready: time::Duration::from_millis(FLASH_READY_TIMEOUT_MS),
+            mass_erase: time::Duration::from_millis(MASS_ERASE_TIMEOUT_MS),
+            halt: time::Duration::from_millis(HALT_TIMEOUT_MS),
+            poll_interval: time::Duration::from_millis(POLL_INTERVAL_MS),
+        }
+    }
+}
+
+/// `WaitStats` - how long `MdmAP::wait_for_status` waited for the condition
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WaitStats {
+    pub elapsed: time::Duration,
+    pub polls: u32,
+}
+
+/// `MassEraseOutcome` - state of the target after `MdmAP::mass_erase` completed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MassEraseOutcome {
+    /// flash erased, System Security is cleared - debug can be started
+    Unsecured,
+    /// flash erased, but System Security is still set
+    StillSecured,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub struct MdmApStatus {
     ///`Flash Mass Erase Acknowledge`
@@ -80,13 +160,25 @@ pub struct MdmApStatus {
     /// Flash Mass Erase in Progress field in MDM AP Control Register. The
     /// Flash Mass Erase Acknowledge is set after Flash control logic has started
     /// the mass erase operation.
-    mass_erase_ack: bool,
-    flash_ready: bool,  // Flash is ready?
-    pub security: bool, // device is secured?
-    system_reset: bool, // 0 = system in reset, 1 = not in reset
-    halt_state: bool,   // core halted ?
-    stop_state: bool,   // stop mode ?
-    wait_state: bool,   // wait mode ?
+    pub mass_erase_ack: bool,
+    pub flash_ready: bool,  // Flash is ready?
+    pub security: bool,     // device is secured?
+    pub system_reset: bool, // 0 = system in reset, 1 = not in reset
+    ///`Mass Erase Enable` mirrors FSEC[MEEN], 0 = mass erase is disabled
+    pub mass_erase_enable: bool,
+    ///`Backdoor Access Key Enable` mirrors FSEC[KEYEN], 1 = backdoor key access enabled
+    pub backdoor_key_enable: bool,
+    ///`LP Enabled` - low power mode is enabled in the SMC, used together with `very_low_power_mode`
+    pub low_power_enabled: bool,
+    ///`Very Low Power Mode` - device is in VLPR, VLPW or VLPS mode
+    pub very_low_power_mode: bool,
+    ///`LLS Mode Exit` - device has exited LLS mode, cleared by POR or debug reset
+    pub lls_exit: bool,
+    ///`VLLSx Modes Exit` - device has exited VLLSx mode, cleared by POR or debug reset
+    pub vlls_exit: bool,
+    pub halt_state: bool,   // core halted ?
+    pub stop_state: bool,   // stop mode ? (core SLEEPDEEP)
+    pub wait_state: bool,   // wait mode ? (core SLEEPING)
     value: u32,
 }
 
@@ -94,6 +186,8 @@ impl MdmApStatus {
     pub fn parse_from_u32(status_lword: u32) -> Self {
         let status_byte0 = ((status_lword >> 0) & 0xFF) as u8;
 
+        let status_byte1 = ((status_lword >> 8) & 0xFF) as u8;
+
         let status_byte2 = ((status_lword >> 16) & 0xFF) as u8;
 
         let mut mdm_status = MdmApStatus::default();
@@ -116,6 +210,30 @@ impl MdmApStatus {
             mdm_status.system_reset = true
         }
 
+        if status_byte0 & 0b0010_0000 != 0 {
+            mdm_status.mass_erase_enable = true
+        }
+
+        if status_byte0 & 0b0100_0000 != 0 {
+            mdm_status.backdoor_key_enable = true
+        }
+
+        if status_byte0 & 0b1000_0000 != 0 {
+            mdm_status.low_power_enabled = true
+        }
+
+        if status_byte1 & 0b0000_0001 != 0 {
+            mdm_status.very_low_power_mode = true
+        }
+
+        if status_byte1 & 0b0000_0010 != 0 {
+            mdm_status.lls_exit = true
+        }
+
+        if status_byte1 & 0b0000_0100 != 0 {
+            mdm_status.vlls_exit = true
+        }
+
         if status_byte2 & 0b0000_0001 != 0 {
             mdm_status.halt_state = true
         }
@@ -131,61 +249,104 @@ impl MdmApStatus {
         mdm_status
     }
 
-    fn compare(&self, other: &MdmApStatus) {
-        let mut no_changes = true;
-        println!("---------------- compare status changes ----------------");
-        if self.mass_erase_ack != other.mass_erase_ack {
-            println!(
-                "mass_erase_ack changed from {} to {}",
-                self.mass_erase_ack, other.mass_erase_ack
-            );
-            no_changes = false;
-        }
-        if self.flash_ready != other.flash_ready {
-            println!(
-                "flash_ready changed from {} to {}",
-                self.flash_ready, other.flash_ready
-            );
-            no_changes = false;
-        }
-        if self.security != other.security {
-            println!(
-                "security changed from {} to {}",
-                self.security, other.security
-            );
-            no_changes = false;
-        }
-        if self.system_reset != other.system_reset {
-            println!(
-                "system_reset changed from {} to {}",
-                self.system_reset, other.system_reset
-            );
-            no_changes = false;
-        }
-        if self.halt_state != other.halt_state {
-            println!(
-                "halt_state changed from {} to {}",
-                self.halt_state, other.halt_state
-            );
-            no_changes = false;
-        }
-        if self.stop_state != other.stop_state {
-            println!(
-                "stop_state changed from {} to {}",
-                self.stop_state, other.stop_state
-            );
-            no_changes = false;
-        }
-        if self.wait_state != other.wait_state {
-            println!(
-                "wait_state changed from {} to {}",
-                self.wait_state, other.wait_state
-            );
-            no_changes = false;
-        }
-        if no_changes == true {
-            println!("mdm_ap_status : no changes");
-        }
+    pub fn mass_erase_ack(&self) -> bool {
+        self.mass_erase_ack
+    }
+
+    pub fn flash_ready(&self) -> bool {
+        self.flash_ready
+    }
+
+    pub fn security(&self) -> bool {
+        self.security
+    }
+
+    pub fn system_reset(&self) -> bool {
+        self.system_reset
+    }
+
+    pub fn mass_erase_enable(&self) -> bool {
+        self.mass_erase_enable
+    }
+
+    pub fn backdoor_key_enable(&self) -> bool {
+        self.backdoor_key_enable
+    }
+
+    pub fn low_power_enabled(&self) -> bool {
+        self.low_power_enabled
+    }
+
+    pub fn very_low_power_mode(&self) -> bool {
+        self.very_low_power_mode
+    }
+
+    pub fn lls_exit(&self) -> bool {
+        self.lls_exit
+    }
+
+    pub fn vlls_exit(&self) -> bool {
+        self.vlls_exit
+    }
+
+    pub fn halt_state(&self) -> bool {
+        self.halt_state
+    }
+
+    pub fn stop_state(&self) -> bool {
+        self.stop_state
+    }
+
+    pub fn wait_state(&self) -> bool {
+        self.wait_state
+    }
+
+    /// `value` - raw longword as read from MDM-AP Status register
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    /// `fields` - all decoded flags with their names, in register bit order
+    pub fn fields(&self) -> [(&'static str, bool); 13] {
+        [
+            ("mass_erase_ack", self.mass_erase_ack),
+            ("flash_ready", self.flash_ready),
+            ("security", self.security),
+            ("system_reset", self.system_reset),
+            ("mass_erase_enable", self.mass_erase_enable),
+            ("backdoor_key_enable", self.backdoor_key_enable),
+            ("low_power_enabled", self.low_power_enabled),
+            ("very_low_power_mode", self.very_low_power_mode),
+            ("lls_exit", self.lls_exit),
+            ("vlls_exit", self.vlls_exit),
+            ("halt_state", self.halt_state),
+            ("stop_state", self.stop_state),
+            ("wait_state", self.wait_state),
+        ]
+    }
+
+    /// `low_power_fields` - low power mode flags, what the target was doing when it was not caught
+    pub fn low_power_fields(&self) -> [(&'static str, bool); 6] {
+        [
+            ("low_power_enabled", self.low_power_enabled),
+            ("very_low_power_mode", self.very_low_power_mode),
+            ("lls_exit", self.lls_exit),
+            ("vlls_exit", self.vlls_exit),
+            ("stop_state", self.stop_state),
+            ("wait_state", self.wait_state),
+        ]
+    }
+
+    /// `compare` - flags changed from `self` to `other`
+    pub fn compare(&self, other: &MdmApStatus) -> Vec<MdmApFieldChange> {
+        diff_fields("MDM_STATUS", &self.fields(), &other.fields())
+    }
+}
+
+impl fmt::Display for MdmApStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MDM_AP_STATUS {:#010X}", self.value)?;
+        write_set_fields(f, &self.fields())
     }
 }
 
@@ -237,50 +398,210 @@ impl MdmApControl {
         mdm_control
     }
 
-    fn compare(&self, other: &MdmApControl) {
-        println!("---------------- compare control changes ----------------");
-        let mut no_changes = true;
-        if self.erase_in_progress != other.erase_in_progress {
-            println!(
-                "erase_in_progress changed from {} to {}",
-                self.erase_in_progress, other.erase_in_progress
-            );
-            no_changes = false;
-        }
-        if self.debug_disable != other.debug_disable {
-            println!(
-                "debug_disable changed from {} to {}",
-                self.debug_disable, other.debug_disable
-            );
-            no_changes = false;
+    /// `new` - empty control word, all requests released
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `from_u32` - decode control longword, bits outside of known fields are kept as is
+    pub fn from_u32(control_lword: u32) -> Self {
+        Self::parse_from_u32(control_lword)
+    }
+
+    /// `to_u32` - encode back to longword for write to MDM-AP Control register
+    pub fn to_u32(&self) -> u32 {
+        self.value
+    }
+
+    /// `encode` - longword for a family implementing `control_bits` (`DeviceFamily::mdm_control_bits`),
+    /// reserved bits are written 0
+    pub fn encode(&self, control_bits: u32) -> u32 {
+        self.value & control_bits
+    }
+
+    fn update_bit(&mut self, bit: u32, set: bool) {
+        if set {
+            self.value |= bit;
+        } else {
+            self.value &= !bit;
         }
-        if self.debug_request != other.debug_request {
-            println!(
-                "debug_request changed from {} to {}",
-                self.debug_request, other.debug_request
-            );
-            no_changes = false;
+    }
+
+    /// `set_mass_erase` - request flash mass erase, cleared by hardware when erase completes
+    pub fn set_mass_erase(&mut self, set: bool) -> &mut Self {
+        self.erase_in_progress = set;
+        self.update_bit(MKE_MDM_CONTROL_FLASH_MASS_ERASE_BIT, set);
+        self
+    }
+
+    /// `set_debug_disable` - disable debug logic
+    pub fn set_debug_disable(&mut self, set: bool) -> &mut Self {
+        self.debug_disable = set;
+        self.update_bit(MKE_MDM_CONTROL_DBG_DIS_BIT, set);
+        self
+    }
+
+    /// `set_debug_request` - force core to debug halt
+    pub fn set_debug_request(&mut self, set: bool) -> &mut Self {
+        self.debug_request = set;
+        self.update_bit(MKE_MDM_CONTROL_DBG_REQ_BIT, set);
+        self
+    }
+
+    /// `set_sys_reset_request` - keep system in reset while set
+    pub fn set_sys_reset_request(&mut self, set: bool) -> &mut Self {
+        self.sys_reset_request = set;
+        self.update_bit(MKE_MDM_CONTROL_SYS_RESET_BIT, set);
+        self
+    }
+
+    /// `set_core_hold` - hold core in reset after system reset is released
+    pub fn set_core_hold(&mut self, set: bool) -> &mut Self {
+        self.core_hold = set;
+        self.update_bit(MKE_MDM_CONTROL_CORE_HOLD_BIT, set);
+        self
+    }
+
+    /// `set_bit` - set or clear one request bit
+    pub fn set_bit(&mut self, bit: MdmApControlBit, set: bool) -> &mut Self {
+        match bit {
+            MdmApControlBit::MassErase => self.set_mass_erase(set),
+            MdmApControlBit::DebugDisable => self.set_debug_disable(set),
+            MdmApControlBit::DebugRequest => self.set_debug_request(set),
+            MdmApControlBit::SysResetRequest => self.set_sys_reset_request(set),
+            MdmApControlBit::CoreHold => self.set_core_hold(set),
         }
-        if self.sys_reset_request != other.sys_reset_request {
-            println!(
-                "sys_reset_request changed from {} to {}",
-                self.sys_reset_request, other.sys_reset_request
-            );
-            no_changes = false;
+    }
+
+    pub fn mass_erase(&self) -> bool {
+        self.erase_in_progress
+    }
+
+    pub fn debug_disable(&self) -> bool {
+        self.debug_disable
+    }
+
+    pub fn debug_request(&self) -> bool {
+        self.debug_request
+    }
+
+    pub fn sys_reset_request(&self) -> bool {
+        self.sys_reset_request
+    }
+
+    pub fn core_hold(&self) -> bool {
+        self.core_hold
+    }
+
+    /// `fields` - all decoded requests with their names, in register bit order
+    pub fn fields(&self) -> [(&'static str, bool); 5] {
+        [
+            ("erase_in_progress", self.erase_in_progress),
+            ("debug_disable", self.debug_disable),
+            ("debug_request", self.debug_request),
+            ("sys_reset_request", self.sys_reset_request),
+            ("core_hold", self.core_hold),
+        ]
+    }
+
+    /// `compare` - requests changed from `self` to `other`
+    pub fn compare(&self, other: &MdmApControl) -> Vec<MdmApFieldChange> {
+        diff_fields("MDM_CONTROL", &self.fields(), &other.fields())
+    }
+}
+
+impl fmt::Display for MdmApControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MDM_AP_CONTROL {:#010X}", self.value)?;
+        write_set_fields(f, &self.fields())
+    }
+}
+
+/// `MdmApFieldChange` - one flag of MDM-AP Status or Control changed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MdmApFieldChange {
+    /// `MDM_STATUS` or `MDM_CONTROL`
+    pub register: &'static str,
+    pub field: &'static str,
+    pub old: bool,
+    pub new: bool,
+}
+
+impl fmt::Display for MdmApFieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{} changed from {} to {}",
+            self.register, self.field, self.old, self.new
+        )
+    }
+}
+
+/// `MdmApDiff` - all changes between two `MdmAP` snapshots
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MdmApDiff {
+    pub changes: Vec<MdmApFieldChange>,
+}
+
+impl MdmApDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// `changed` - flag `field` of `register` is in the diff
+    pub fn changed(&self, register: &str, field: &str) -> Option<&MdmApFieldChange> {
+        self.changes
+            .iter()
+            .find(|change| change.register == register && change.field == field)
+    }
+}
+
+impl fmt::Display for MdmApDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.changes.is_empty() {
+            return write!(f, "mdm_ap : no changes");
         }
-        if self.core_hold != other.core_hold {
-            println!(
-                "core_hold changed from {} to {}",
-                self.core_hold, other.core_hold
-            );
-            no_changes = false;
+        for (index, change) in self.changes.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", change)?;
         }
-        if no_changes == true {
-            println!("mdm_ap_control : no changes");
+        Ok(())
+    }
+}
+
+fn diff_fields(
+    register: &'static str,
+    old: &[(&'static str, bool)],
+    new: &[(&'static str, bool)],
+) -> Vec<MdmApFieldChange> {
+    old.iter()
+        .zip(new.iter())
+        .filter(|((_, old), (_, new))| old != new)
+        .map(|((field, old), (_, new))| MdmApFieldChange {
+            register,
+            field,
+            old: *old,
+            new: *new,
+        })
+        .collect()
+}
+
+pub(crate) fn write_set_fields(f: &mut fmt::Formatter, fields: &[(&'static str, bool)]) -> fmt::Result {
+    write!(f, " [")?;
+    let mut first = true;
+    for (field, _) in fields.iter().filter(|(_, set)| *set) {
+        if !first {
+            write!(f, " ")?;
         }
+        write!(f, "{}", field)?;
+        first = false;
     }
+    write!(f, "]")
 }
 
+
 ///`MKE MDM_AP`
 /// `miscellaneous debug module (MDM)` is implemented on this device, which contains
 /// the DAP control and status registers
@@ -293,28 +614,59 @@ impl MdmApControl {
 /// A[3:2] = 2’b01 selects the Control Register
 /// SELECT[7:4] = 0xF selects the bank with IDR
 /// A[3:2] = 2’b11 selects the IDR Register
-#[derive(Debug, PartialEq, Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct MdmAP {
+    /// AccessPort index of MDM-AP, `MKE_MDM_AP_PORT` or found by `scan_aps`
+    pub ap: u8,
     pub status: MdmApStatus,
     pub control: MdmApControl,
+    /// `control_bits` - MDM-AP Control bits of the device family, `DeviceFamily::mdm_control_bits`
+    pub control_bits: u32,
+    /// `control_write_retries` - attempts of `modify_control` before report bit not latched
+    pub control_write_retries: u32,
+    pub timeouts: MdmTimeouts,
+}
+
+impl Default for MdmAP {
+    fn default() -> Self {
+        Self {
+            ap: MKE_MDM_AP_PORT,
+            status: MdmApStatus::default(),
+            control: MdmApControl::default(),
+            control_bits: MDM_CONTROL_ALL_BITS,
+            control_write_retries: MDM_CONTROL_WRITE_RETRIES,
+            timeouts: MdmTimeouts::default(),
+        }
+    }
+}
+
+impl fmt::Display for MdmAP {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", self.status, self.control)
+    }
 }
 
 impl MdmAP {
     /// `read_mdm_ap_register` - get two longword of MDM Status & Control register
     ///  Return packed-struct (bits-field) `MdmAP` with currently status of MDM
-    pub fn read_mdm_ap_register(
-        iface: &mut dyn ArmProbeInterface,
+    pub fn read_mdm_ap_register<T: Transport + ?Sized>(
+        iface: &mut T,
+        print: bool,
+    ) -> Result<Self, Error> {
+        Self::read_mdm_ap_register_at(iface, MKE_MDM_AP_PORT, print)
+    }
+
+    /// `read_mdm_ap_register_at` - `read_mdm_ap_register` of MDM-AP at AccessPort `ap`
+    pub fn read_mdm_ap_register_at<T: Transport + ?Sized>(
+        iface: &mut T,
+        ap: u8,
         print: bool,
     ) -> Result<Self, Error> {
-        let mdm_ap_status = iface
-            .read_raw_ap_register(MKE_MDM_AP_PORT, MKE_MDM_STATUS)
-            .map_err(|err| Error::MdmExample("iface.read_raw_ap_register".to_string()))?;
-        let mdm_ap_control = iface
-            .read_raw_ap_register(MKE_MDM_AP_PORT, MKE_MDM_CONTROL)
-            .map_err(|err| Error::MdmExample("iface.read_raw_ap_register".to_string()))?;
+        let mdm_ap_status = iface.read_ap_register(ap, MKE_MDM_STATUS)?;
+        let mdm_ap_control = iface.read_ap_register(ap, MKE_MDM_CONTROL)?;
 
         if (print) {
-            println!(
+            log::debug!(
                 "mdm_ap_status  {:04X}, mdm_ap_control  {:04X}",
                 &mdm_ap_status, &mdm_ap_control
             );
@@ -323,104 +675,206 @@ impl MdmAP {
         let status = MdmApStatus::parse_from_u32(mdm_ap_status);
         let control = MdmApControl::parse_from_u32(mdm_ap_control);
 
-        let mdm_ap = Self { status, control };
+        let mdm_ap = Self {
+            ap,
+            status,
+            control,
+            ..Default::default()
+        };
 
         Ok(mdm_ap)
     }
 
-    /// `write_mdm_ap_control_bit` - write only one bit, hold another without changes
-    ///  Return packed-struct (bits-field) `MdmAP` with currently status of MDM
-    pub fn write_mdm_ap_control_bit(
+    /// `write_control` - write `MdmApControl` to MDM-AP Control register, keep it as current control
+    pub fn write_control<T: Transport + ?Sized>(
         &mut self,
-        iface: &mut dyn ArmProbeInterface,
+        iface: &mut T,
+        control: &MdmApControl,
+    ) -> Result<(), Error> {
+        iface.write_ap_register(self.ap, MKE_MDM_CONTROL, control.encode(self.control_bits))?;
+        self.control = *control;
+        Ok(())
+    }
+
+    /// `modify_control` - read-modify-write of MDM-AP Control register
+    ///
+    /// Control is re-read from target before every write (cache may be stale, mass erase bit
+    /// self-clears), changed bits are verified by readback. Repeated up to `control_write_retries`,
+    /// return `Error::ControlBitNotLatched` if hardware still not accept the value.
+    /// A mass erase request is written once by `write_ap_register_no_retry` and not verified.
+    /// Change of a bit outside `control_bits` is `Error::Unsupported`.
+    pub fn modify_control<T, F>(
+        &mut self,
+        iface: &mut T,
+        modify: F,
+    ) -> Result<MdmApControl, Error>
+    where
+        T: Transport + ?Sized,
+        F: Fn(&mut MdmApControl),
+    {
+        let attempts = self.control_write_retries.max(1);
+        let mut expected: u32 = 0;
+        let mut readback: u32 = 0;
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                thread::sleep(time::Duration::from_millis(1));
+            }
+            let current = self.read_control(iface)?;
+            let mut wanted = current;
+            modify(&mut wanted);
+            let changed_mask = current.to_u32() ^ wanted.to_u32();
+            if changed_mask & !self.control_bits != 0 {
+                return Err(Error::Unsupported("MDM-AP Control bit not implemented by the device family"));
+            }
+
+            if wanted.mass_erase() && !current.mass_erase() {
+                /* written once, not verified: the request self-clears and a repeated write
+                would start the erase again, progress is seen by `mass_erase_ack` */
+                iface.write_ap_register_no_retry(self.ap, MKE_MDM_CONTROL, wanted.encode(self.control_bits))?;
+                self.control = wanted;
+                return Ok(wanted);
+            }
+
+            self.write_control(iface, &wanted)?;
+
+            let latched = self.read_control(iface)?;
+            expected = wanted.encode(self.control_bits);
+            readback = latched.encode(self.control_bits);
+            if (readback ^ expected) & changed_mask == 0 {
+                return Ok(latched);
+            }
+        }
+        Err(Error::ControlBitNotLatched {
+            expected,
+            readback,
+            attempts,
+        })
+    }
+
+    /// `set_control_bit` - verified set of one MDM-AP Control bit
+    pub fn set_control_bit<T: Transport + ?Sized>(
+        &mut self,
+        iface: &mut T,
+        bit: MdmApControlBit,
+    ) -> Result<MdmApControl, Error> {
+        self.modify_control(iface, |control| {
+            control.set_bit(bit, true);
+        })
+    }
+
+    /// `clear_control_bit` - verified clear of one MDM-AP Control bit
+    pub fn clear_control_bit<T: Transport + ?Sized>(
+        &mut self,
+        iface: &mut T,
+        bit: MdmApControlBit,
+    ) -> Result<MdmApControl, Error> {
+        self.modify_control(iface, |control| {
+            control.set_bit(bit, false);
+        })
+    }
+
+    /// `set_debug_disable` - verified set / clear of Debug Disable (DBG_DIS)
+    ///
+    /// `Error::DebugDisabled` if the bit can't be cleared, hardware keeps debug disabled.
+    pub fn set_debug_disable<T: Transport + ?Sized>(
+        &mut self,
+        iface: &mut T,
+        disable: bool,
+    ) -> Result<MdmApControl, Error> {
+        if disable {
+            return self.set_control_bit(iface, MdmApControlBit::DebugDisable);
+        }
+        self.clear_control_bit(iface, MdmApControlBit::DebugDisable)
+            .map_err(|err| match err {
+                Error::ControlBitNotLatched { readback, .. } => Error::DebugDisabled { control: readback },
+                err => err,
+            })
+    }
+
+    /// `read_control` - read MDM-AP Control register only, refresh cached control
+    pub fn read_control<T: Transport + ?Sized>(
+        &mut self,
+        iface: &mut T,
+    ) -> Result<MdmApControl, Error> {
+        let mdm_ap_control = iface.read_ap_register(self.ap, MKE_MDM_CONTROL)?;
+        self.control = MdmApControl::parse_from_u32(mdm_ap_control);
+        Ok(self.control)
+    }
+
+    /// `write_mdm_ap_control_bit` - set `bit` mask by verified `modify_control`, other bits kept as read
+    pub fn write_mdm_ap_control_bit<T: Transport + ?Sized>(
+        &mut self,
+        iface: &mut T,
         bit: u32,
     ) -> Result<(), Error> {
-        let change_one_bit: u32 = self.control.value | bit;
-        iface
-            .write_raw_ap_register(MKE_MDM_AP_PORT, MKE_MDM_CONTROL, change_one_bit)
-            .map_err(|err| {
-                Error::MdmExample(format!(
-                    "While write_mdm_ap_control_bit {:#06X}, old_value {:#06X}, : error {:?}, ",
-                    bit, self.control.value, err
-                ))
-            })?;
+        self.modify_control(iface, |control| {
+            *control = MdmApControl::parse_from_u32(control.to_u32() | bit);
+        })?;
         Ok(())
     }
 
-    /// `write_mdm_ap_control_clear_bit` - clear one bit
-    pub fn write_mdm_ap_control_clear_bit(
+    /// `write_mdm_ap_control_clear_bit` - clear `bit` mask by verified `modify_control`
+    pub fn write_mdm_ap_control_clear_bit<T: Transport + ?Sized>(
         &mut self,
-        iface: &mut dyn ArmProbeInterface,
+        iface: &mut T,
         bit: u32,
     ) -> Result<(), Error> {
-        let clear_one_bit: u32 = self.control.value & !bit;
-        iface
-            .write_raw_ap_register(MKE_MDM_AP_PORT, MKE_MDM_CONTROL, clear_one_bit)
-            .map_err(|err| {
-                Error::MdmExample(format!(
-                    "While write_mdm_ap_control_bit {:#06X}, old_value {:#06X}, : error {:?}, ",
-                    bit, self.control.value, err
-                ))
-            })?;
+        self.modify_control(iface, |control| {
+            *control = MdmApControl::parse_from_u32(control.to_u32() & !bit);
+        })?;
         Ok(())
     }
 
-    /// `write_mdm_ap_control_new` - write new value to mdm_ap
-    pub fn write_mdm_ap_control_new(
+    /// `write_mdm_ap_control_new` - write new value by verified `modify_control`
+    pub fn write_mdm_ap_control_new<T: Transport + ?Sized>(
         &mut self,
-        iface: &mut dyn ArmProbeInterface,
+        iface: &mut T,
         value: u32,
     ) -> Result<(), Error> {
-        iface
-            .write_raw_ap_register(MKE_MDM_AP_PORT, MKE_MDM_CONTROL, value)
-            .map_err(|err| {
-                Error::MdmExample(format!(
-                    "While write_mdm_ap_control_new {:#06X}, old_value {:#06X}, : error {:?}, ",
-                    value, self.control.value, err
-                ))
-            })?;
+        self.modify_control(iface, |control| {
+            *control = MdmApControl::parse_from_u32(value);
+        })?;
         Ok(())
     }
 
-    pub fn compare(&self, updated: &MdmAP) {
-        self.status.compare(&updated.status);
-        self.control.compare(&updated.control);
+    /// `compare` - structured diff from `self` to `updated`
+    pub fn compare(&self, updated: &MdmAP) -> MdmApDiff {
+        let mut changes = self.status.compare(&updated.status);
+        changes.extend(self.control.compare(&updated.control));
+        MdmApDiff { changes }
     }
 
-    pub fn read_mdm_ap_idr(&self, iface: &mut dyn ArmProbeInterface) -> Result<u32, Error> {
-        let idr = iface
-            .read_raw_ap_register(MKE_MDM_AP_PORT, MKE_MDM_IDR_REG)
-            .map_err(|err| Error::MdmExample("iface.read_raw_ap_register".to_string()))?;
+    pub fn read_mdm_ap_idr<T: Transport + ?Sized>(&self, iface: &mut T) -> Result<u32, Error> {
+        let idr = iface.read_ap_register(self.ap, MKE_MDM_IDR_REG)?;
         Ok(idr)
     }
 
+    /// `check_mdm_ap_idr` - read MDM-AP IDR, `Error::IdrMismatch` if it is not `IDR_REG_CHECK_VALUE`
+    pub fn check_mdm_ap_idr<T: Transport + ?Sized>(&self, iface: &mut T) -> Result<u32, Error> {
+        self.check_mdm_ap_idr_value(iface, IDR_REG_CHECK_VALUE)
+    }
+
+    /// `check_mdm_ap_idr_value` - read MDM-AP IDR, `Error::IdrMismatch` if it is not `expected`
+    pub fn check_mdm_ap_idr_value<T: Transport + ?Sized>(&self, iface: &mut T, expected: u32) -> Result<u32, Error> {
+        let idr = self.read_mdm_ap_idr(iface)?;
+        if idr != expected {
+            return Err(Error::IdrMismatch { expected, found: idr });
+        }
+        Ok(idr)
+    }
+
+    /// `print` - log current snapshot at debug level
     pub fn print(&self) {
-        println!("----------------MDM_AP_STATUS----------------");
-        println!("mass_erase_ack is {:?}", &self.status.mass_erase_ack);
-        println!("flash_ready is {:?}", &self.status.flash_ready);
-        println!("security is {:?}", &self.status.security);
-        println!("system_reset is {:?}", &self.status.system_reset);
-        println!("halt_state is {:?}", &self.status.halt_state);
-        println!("stop_state is {:?}", &self.status.stop_state);
-        println!("wait_state is {:?}", &self.status.wait_state);
-        println!("--------------------------------------------");
-
-        println!("----------------MDM_AP_CONTROL----------------");
-        println!("erase_in_progress is {:?}", &self.control.erase_in_progress);
-        println!("debug_disable is {:?}", &self.control.debug_disable);
-        println!("debug_request is {:?}", &self.control.debug_request);
-        println!("sys_reset_request is {:?}", &self.control.sys_reset_request);
-        println!("core_hold is {:?}", &self.control.core_hold);
-        println!("--------------------------------------------");
+        log::debug!("{}", self);
     }
 
     /// `refresh_mdm_ap` - read & store `MdmAP` in MKExxZZ
-    pub fn refresh_mdm_ap(
+    pub fn refresh_mdm_ap<T: Transport + ?Sized>(
         &mut self,
-        mut iface: &mut dyn ArmProbeInterface,
+        mut iface: &mut T,
         print: bool,
     ) -> Result<Self, Error> {
-        let mut new_mdm_ap = MdmAP::read_mdm_ap_register(iface.deref_mut(), print)?;
+        let mut new_mdm_ap = MdmAP::read_mdm_ap_register_at(iface.deref_mut(), self.ap, print)?;
 
         if (print) {
             new_mdm_ap.print();
@@ -432,84 +886,374 @@ impl MdmAP {
         Ok(new_mdm_ap)
     }
 
-    pub fn refresh_and_compare_mdm_ap(
+    /// `refresh_and_compare_mdm_ap` - read `MdmAP`, return diff to the stored one, store new
+    pub fn refresh_and_compare_mdm_ap<T: Transport + ?Sized>(
         &mut self,
-        mut iface: &mut dyn ArmProbeInterface,
+        mut iface: &mut T,
         track_reason: String,
-    ) -> Result<Self, Error> {
-        let updated_mdm_ap = MdmAP::read_mdm_ap_register(iface.deref_mut(), true)?;
+    ) -> Result<MdmApDiff, Error> {
+        let updated_mdm_ap = MdmAP::read_mdm_ap_register_at(iface.deref_mut(), self.ap, true)?;
 
-        println!("{}", &track_reason);
-
-        self.compare(&updated_mdm_ap);
+        let diff = self.compare(&updated_mdm_ap);
+        log::debug!("{} : {}", track_reason.trim(), diff);
 
         self.status = updated_mdm_ap.status;
         self.control = updated_mdm_ap.control;
 
-        Ok(updated_mdm_ap)
+        Ok(diff)
     }
 
     
 
-    pub fn mdm_ap_reset_keep(
+    /// `wait_for_status` - poll MDM-AP until `predicate` is true or `timeout` is over
+    ///
+    /// MDM-AP is read at once, then every `interval`. Return time and polls it took,
+    /// `Error::Timeout` with `operation` if deadline passed.
+    pub fn wait_for_status<T, P>(
         &mut self,
-        mut iface: &mut dyn ArmProbeInterface,
-    ) -> Result<(), Error> {
-        self.write_mdm_ap_control_new(iface.deref_mut(), MKE_MDM_CONTROL_SYS_RESET_BIT)?;
-        let mut system_is_reset: bool = false;
-        for retry in 0..20 {
-            thread::sleep(time::Duration::from_millis(1));
-            self.refresh_mdm_ap(iface.deref_mut(), false)?;
-            if (self.status.value & MKE_MDM_STATUS_SYSTEM_RESET_BIT == 0) {
-                println!(
-                    " Reset: MPM_AP.MKE_MDM_STATUS_SYSTEM_RESET_BIT = 0 (System is IN reset) "
-                );
-                system_is_reset = true;
-                break;
+        iface: &mut T,
+        operation: &'static str,
+        predicate: P,
+        timeout: time::Duration,
+        interval: time::Duration,
+    ) -> Result<WaitStats, Error>
+    where
+        T: Transport + ?Sized,
+        P: Fn(&MdmAP) -> bool,
+    {
+        let start = time::Instant::now();
+        let deadline = start + timeout;
+        let mut polls: u32 = 0;
+        loop {
+            self.refresh_mdm_ap(iface, false)?;
+            polls += 1;
+            if predicate(self) {
+                return Ok(WaitStats {
+                    elapsed: start.elapsed(),
+                    polls,
+                });
             }
+            let now = time::Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout {
+                    operation,
+                    elapsed: start.elapsed(),
+                });
+            }
+            thread::sleep(interval.min(deadline - now));
         }
-        if (!system_is_reset) {
-            return Err(Error::MdmExample(
-                " Reset: System not reseting reset after 20ms".into(),
-            ));
-        }
+    }
+
+    pub fn mdm_ap_reset_keep<T: Transport + ?Sized>(
+        &mut self,
+        mut iface: &mut T,
+    ) -> Result<(), Error> {
+        let mut control = MdmApControl::new();
+        control.set_sys_reset_request(true);
+        self.write_control(iface.deref_mut(), &control)?;
+        let stats = self.wait_for_status(
+            iface.deref_mut(),
+            "system reset assert",
+            |mdm_ap| !mdm_ap.status.system_reset,
+            self.timeouts.reset,
+            self.timeouts.poll_interval,
+        )?;
+        log::debug!(
+            " Reset: MPM_AP.MKE_MDM_STATUS_SYSTEM_RESET_BIT = 0 (System is IN reset) after {}us, {} polls ",
+            stats.elapsed.as_micros(),
+            stats.polls
+        );
 
         Ok(())
     }
 
 
-    pub fn mdm_ap_clear_reset_bit(
+    pub fn mdm_ap_clear_reset_bit<T: Transport + ?Sized>(
         &mut self,
-        mut iface: &mut dyn ArmProbeInterface,
+        mut iface: &mut T,
     ) -> Result<(), Error> {
-        self.write_mdm_ap_control_clear_bit(iface.deref_mut(), MKE_MDM_CONTROL_SYS_RESET_BIT)?;
+        self.clear_control_bit(iface.deref_mut(), MdmApControlBit::SysResetRequest)?;
         Ok(())
     }
 
-    pub fn is_mdm_flash_ready(
+    /// `wait_reset_released` - wait MDM-AP Status System Reset = 1 (system out of reset)
+    pub fn wait_reset_released<T: Transport + ?Sized>(
+        &mut self,
+        iface: &mut T,
+    ) -> Result<WaitStats, Error> {
+        self.wait_for_status(
+            iface,
+            "system reset release",
+            |mdm_ap| mdm_ap.status.system_reset,
+            self.timeouts.reset,
+            self.timeouts.poll_interval,
+        )
+    }
+
+    pub fn is_mdm_flash_ready<T: Transport + ?Sized>(
         &mut self,
-        mut iface: &mut dyn ArmProbeInterface,
+        mut iface: &mut T,
     ) -> Result<(), Error> {
         self.refresh_mdm_ap(iface.deref_mut(), true)?;
-        println!(" Waiting mdm_ap_flash_ready_bit ");
-        let mut flash_ready = false;
-        for retry in 0..20 {
-            self.refresh_mdm_ap(iface.deref_mut(), false)?;
-            if (self.status.value & MKE_MDM_STATUS_FLASH_READY_BIT != 0) {
-                flash_ready = true;
-                println!(" MPM_AP.flash_ready_bit = 1 (Flash is ready) ");
-                break;
-            }
+        log::debug!(" Waiting mdm_ap_flash_ready_bit ");
+        let stats = self.wait_for_status(
+            iface.deref_mut(),
+            "flash ready",
+            |mdm_ap| mdm_ap.status.flash_ready,
+            self.timeouts.flash_ready,
+            self.timeouts.poll_interval,
+        )?;
+        log::debug!(
+            " MPM_AP.flash_ready_bit = 1 (Flash is ready) after {}us, {} polls ",
+            stats.elapsed.as_micros(),
+            stats.polls
+        );
+        self.refresh_mdm_ap(iface.deref_mut(), true)?;
+
+        Ok(())
+    }
+
+    /// `wait_core_halted` - wait MDM-AP Status `halt_state`
+    pub fn wait_core_halted<T: Transport + ?Sized>(
+        &mut self,
+        iface: &mut T,
+    ) -> Result<WaitStats, Error> {
+        self.wait_for_status(
+            iface,
+            "core halt",
+            |mdm_ap| mdm_ap.status.halt_state,
+            self.timeouts.halt,
+            self.timeouts.poll_interval,
+        )
+    }
+
+    /// `mass_erase` - erase all flash by MDM-AP to unsecure the target
+    ///
+    /// Sequence: check Mass Erase Enable (FSEC[MEEN]), keep system in reset, wait flash ready,
+    /// set Flash Mass Erase in Progress, wait `mass_erase_ack`, wait control bit self-clear,
+    /// re-check System Security. System reset is left asserted, so the caller may continue
+    /// with the debug request on the erased part.
+    pub fn mass_erase<T: Transport + ?Sized>(
+        &mut self,
+        mut iface: &mut T,
+    ) -> Result<MassEraseOutcome, Error> {
+        self.refresh_mdm_ap(iface.deref_mut(), false)?;
+        if !self.status.mass_erase_enable {
+            return Err(Error::MassEraseDisabled);
         }
+
+        self.mdm_ap_reset_keep(iface.deref_mut())?;
+        self.is_mdm_flash_ready(iface.deref_mut())?;
+
+        self.set_control_bit(iface.deref_mut(), MdmApControlBit::MassErase)?;
+        log::info!(" Mass erase: MDM_AP.FLASH_MASS_ERASE_BIT set, waiting ack ");
+
+        self.wait_for_status(
+            iface.deref_mut(),
+            "mass erase ack",
+            |mdm_ap| mdm_ap.status.mass_erase_ack,
+            self.timeouts.mass_erase,
+            self.timeouts.poll_interval,
+        )?;
+        let stats = self.wait_for_status(
+            iface.deref_mut(),
+            "mass erase",
+            |mdm_ap| !mdm_ap.control.erase_in_progress,
+            self.timeouts.mass_erase,
+            time::Duration::from_millis(10),
+        )?;
+        log::info!(
+            " Mass erase: done in {}ms, {} polls ",
+            stats.elapsed.as_millis(),
+            stats.polls
+        );
+
         self.refresh_mdm_ap(iface.deref_mut(), true)?;
-        if (!flash_ready) {
-            return Err(Error::MdmExample(
-                "Flash module not ready! MKE_MDM_STATUS_FLASH_READY_BIT = 1".into(),
-            ));
+        if self.status.security {
+            Ok(MassEraseOutcome::StillSecured)
+        } else {
+            Ok(MassEraseOutcome::Unsecured)
         }
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_flags_and_getters() {
+        let status = MdmApStatus::parse_from_u32(0x0001_0022);
+        assert!(status.flash_ready && status.mass_erase_enable && status.halt_state);
+        assert!(!status.security && !status.system_reset && !status.mass_erase_ack);
+        assert_eq!(status.flash_ready(), status.flash_ready);
+        assert_eq!(status.value(), 0x0001_0022);
+
+        let secured_in_reset = MdmApStatus::parse_from_u32(MKE_MDM_STATUS_SYSTEM_SECURITY_BIT | MKE_MDM_STATUS_SYSTEM_RESET_BIT);
+        assert!(secured_in_reset.security() && secured_in_reset.system_reset());
+        assert!(!secured_in_reset.flash_ready() && !secured_in_reset.halt_state());
     }
 
-    
+    #[test]
+    fn status_low_power_flags() {
+        let status = MdmApStatus::parse_from_u32(0x0006_0780);
+        let set: Vec<&str> = status.low_power_fields().iter().filter(|(_, set)| *set).map(|(name, _)| *name).collect();
+        assert_eq!(set.len(), 6, "{:?}", set);
+        assert!(status.low_power_enabled && status.very_low_power_mode);
+        assert!(status.lls_exit && status.vlls_exit && status.stop_state && status.wait_state);
+        assert!(!status.halt_state);
+    }
+
+    #[test]
+    fn control_encodes_back_unchanged() {
+        assert_eq!(MdmApControl::from_u32(0x0000_001F).to_u32(), 0x0000_001F);
+        /* bits outside the known fields survive a read-modify-write */
+        assert_eq!(MdmApControl::from_u32(0xFFFF_FFE0).to_u32(), 0xFFFF_FFE0);
+
+        let control = MdmApControl::from_u32(MKE_MDM_CONTROL_DBG_REQ_BIT | MKE_MDM_CONTROL_CORE_HOLD_BIT);
+        assert!(control.debug_request() && control.core_hold());
+        assert!(!control.mass_erase() && !control.debug_disable() && !control.sys_reset_request());
+    }
+
+    fn sim_mdm_ap(sim: &mut SimTarget) -> MdmAP {
+        MdmAP::read_mdm_ap_register(sim, false).unwrap()
+    }
+
+    #[test]
+    fn modify_control_retries_until_latched() {
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        let mut mdm_ap = sim_mdm_ap(&mut sim);
+        sim.control_write_drops = 2;
+        let control = mdm_ap.set_control_bit(&mut sim, MdmApControlBit::DebugRequest).unwrap();
+        assert!(control.debug_request());
+        assert_eq!(sim.control_writes(), 3);
+    }
+
+    #[test]
+    fn modify_control_not_latched() {
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        let mut mdm_ap = sim_mdm_ap(&mut sim);
+        mdm_ap.control_write_retries = 4;
+        sim.control_write_drops = u32::MAX;
+        let result = mdm_ap.set_control_bit(&mut sim, MdmApControlBit::CoreHold);
+        assert!(
+            matches!(
+                result,
+                Err(Error::ControlBitNotLatched { expected: MKE_MDM_CONTROL_CORE_HOLD_BIT, readback: 0, attempts: 4 })
+            ),
+            "{:?}",
+            result
+        );
+        assert_eq!(sim.control_writes(), 4);
+    }
+
+    #[test]
+    fn legacy_helper_writes_mass_erase_once() {
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        let mut mdm_ap = sim_mdm_ap(&mut sim);
+        /* the request is lost, it must not be sent again */
+        sim.control_write_drops = 1;
+        mdm_ap.write_mdm_ap_control_bit(&mut sim, MKE_MDM_CONTROL_FLASH_MASS_ERASE_BIT).unwrap();
+        assert_eq!(sim.control_writes(), 1);
+
+        mdm_ap.refresh_mdm_ap(&mut sim, false).unwrap();
+        mdm_ap.set_control_bit(&mut sim, MdmApControlBit::MassErase).unwrap();
+        assert_eq!(sim.control_writes(), 2);
+    }
+
+    #[test]
+    fn wait_counts_polls_until_flash_ready() {
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        /* ready 10 accesses after reset assert, every poll reads Status and Control */
+        sim.flash_ready_delay = Some(10);
+        let mut mdm_ap = sim_mdm_ap(&mut sim);
+        mdm_ap.mdm_ap_reset_keep(&mut sim).unwrap();
+        assert!(!mdm_ap.status.flash_ready);
+        let stats = mdm_ap
+            .wait_for_status(
+                &mut sim,
+                "flash ready",
+                |mdm_ap| mdm_ap.status.flash_ready,
+                time::Duration::from_secs(1),
+                time::Duration::ZERO,
+            )
+            .unwrap();
+        assert_eq!(stats.polls, 5);
+    }
+
+    #[test]
+    fn wait_returns_at_first_poll_if_already_true() {
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        let mut mdm_ap = sim_mdm_ap(&mut sim);
+        let stats = mdm_ap
+            .wait_for_status(&mut sim, "flash ready", |mdm_ap| mdm_ap.status.flash_ready, time::Duration::ZERO, time::Duration::ZERO)
+            .unwrap();
+        assert_eq!(stats.polls, 1);
+    }
+
+    #[test]
+    fn wait_timeout() {
+        let mut sim = SimTarget::new(SimScenario::FlashNeverReady);
+        let mut mdm_ap = sim_mdm_ap(&mut sim);
+        mdm_ap.timeouts.flash_ready = time::Duration::from_millis(5);
+        let result = mdm_ap.is_mdm_flash_ready(&mut sim);
+        match result {
+            Err(Error::Timeout { operation: "flash ready", elapsed }) => assert!(elapsed >= time::Duration::from_millis(5)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn diff_of_one_changed_bit() {
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        let mut mdm_ap = sim_mdm_ap(&mut sim);
+        assert!(mdm_ap.refresh_and_compare_mdm_ap(&mut sim, "unchanged".to_string()).unwrap().is_empty());
+
+        sim.secured = true;
+        let diff = mdm_ap.refresh_and_compare_mdm_ap(&mut sim, "secured".to_string()).unwrap();
+        assert_eq!(
+            diff.changes,
+            vec![MdmApFieldChange { register: "MDM_STATUS", field: "security", old: false, new: true }]
+        );
+        assert!(diff.changed("MDM_STATUS", "security").is_some());
+        assert!(diff.changed("MDM_CONTROL", "debug_request").is_none());
+        assert_eq!(diff.to_string(), "MDM_STATUS.security changed from false to true");
+    }
+
+    #[test]
+    fn control_encode_by_family() {
+        let kl2x = find_device_family("KL2x").unwrap();
+        let ke1xz = find_device_family("KE1xZ").unwrap();
+        let control = MdmApControl::from_u32(0xFFFF_FF00 | 0x20 | MKE_MDM_CONTROL_DBG_REQ_BIT);
+        assert_eq!(control.encode(kl2x.mdm_control_bits), 0x20 | MKE_MDM_CONTROL_DBG_REQ_BIT);
+        assert_eq!(control.encode(ke1xz.mdm_control_bits), MKE_MDM_CONTROL_DBG_REQ_BIT);
+    }
+
+    #[test]
+    fn vlls_debug_request_only_on_kl2x() {
+        /* VLLDBGREQ, bit 5 */
+        let vlls_debug_request = |control: &mut MdmApControl| *control = MdmApControl::from_u32(control.to_u32() | 0x20);
+
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        sim.control_bits = find_device_family("KL2x").unwrap().mdm_control_bits;
+        let mut mdm_ap = sim_mdm_ap(&mut sim);
+        mdm_ap.control_bits = sim.control_bits;
+        assert_eq!(mdm_ap.modify_control(&mut sim, vlls_debug_request).unwrap().to_u32(), 0x20);
+
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        let mut mdm_ap = sim_mdm_ap(&mut sim);
+        mdm_ap.control_bits = find_device_family("KE1xZ").unwrap().mdm_control_bits;
+        let result = mdm_ap.modify_control(&mut sim, vlls_debug_request);
+        assert!(matches!(result, Err(Error::Unsupported(_))), "{:?}", result);
+        assert_eq!(sim.control_writes(), 0);
+    }
+
+    #[test]
+    fn control_setters() {
+        let mut control = MdmApControl::new();
+        control.set_sys_reset_request(true).set_debug_request(true);
+        assert_eq!(control.to_u32(), MKE_MDM_CONTROL_SYS_RESET_BIT | MKE_MDM_CONTROL_DBG_REQ_BIT);
+        control.set_bit(MdmApControlBit::SysResetRequest, false);
+        assert_eq!(control.to_u32(), MKE_MDM_CONTROL_DBG_REQ_BIT);
+    }
 }
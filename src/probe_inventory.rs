@@ -0,0 +1,177 @@
+use super::*;
+
+use std::fmt;
+use std::io;
+
+use probe_rs::{DebugProbeInfo, Probe};
+
+/// `ProbeEntry` - one connected debug probe of `probe_inventory`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeEntry {
+    pub info: DebugProbeInfo,
+    /// firmware version, `None` - not an ST-Link or not readable
+    pub firmware: Option<String>,
+    /// probe can't be opened, another debugger holds it
+    pub in_use: bool,
+}
+
+impl ProbeEntry {
+    /// `to_json` - JSON object, field names are the station software interface
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"type\":{},\"vid\":\"{:04X}\",\"pid\":\"{:04X}\",\"serial\":{},\"identifier\":{},\"firmware\":{},\"in_use\":{}}}",
+            json_string(probe_type_name(&self.info.probe_type)),
+            self.info.vendor_id,
+            self.info.product_id,
+            self.info.serial_number.as_deref().map_or("null".to_string(), json_string),
+            json_string(&self.info.identifier),
+            self.firmware.as_deref().map_or("null".to_string(), json_string),
+            self.in_use
+        )
+    }
+}
+
+impl fmt::Display for ProbeEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<10} {:04X}:{:04X}  SN {:<26} FW {:<12} {}  {}",
+            probe_type_name(&self.info.probe_type),
+            self.info.vendor_id,
+            self.info.product_id,
+            self.info.serial_number.as_deref().unwrap_or("?"),
+            self.firmware.as_deref().unwrap_or("?"),
+            if self.in_use { "in use" } else { "free  " },
+            self.info.identifier
+        )
+    }
+}
+
+/// `json_string` - quoted and escaped JSON string
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// `probe_inventory` - all probes of `Probe::list_all`, each opened once to see if it is in use
+///
+/// Probes held by another debugger are left alone, their firmware version is not read.
+pub fn probe_inventory() -> Vec<ProbeEntry> {
+    Probe::list_all()
+        .into_iter()
+        .map(|info| {
+            let in_use = match info.open() {
+                /* opened and closed again at the end of the arm */
+                Ok(_probe) => false,
+                Err(err) => {
+                    log::debug!("{} can't be opened: {}", describe_probe(&info), err);
+                    true
+                }
+            };
+            let firmware = if !in_use && is_stlink(info.vendor_id, info.product_id) {
+                match read_stlink_version(info.product_id, info.serial_number.as_deref()) {
+                    Ok(version) => Some(version.to_string()),
+                    Err(err) => {
+                        log::debug!("{} firmware version: {}", describe_probe(&info), report(&err));
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            ProbeEntry { info, firmware, in_use }
+        })
+        .collect()
+}
+
+/// `write_inventory` - human readable table, or JSON array if `json`
+pub fn write_inventory<W: io::Write>(entries: &[ProbeEntry], json: bool, output: &mut W) -> Result<(), Error> {
+    if json {
+        let objects: Vec<String> = entries.iter().map(ProbeEntry::to_json).collect();
+        writeln!(output, "[{}]", objects.join(","))?;
+        return Ok(());
+    }
+    if entries.is_empty() {
+        writeln!(output, "no debug probes connected")?;
+    }
+    for entry in entries {
+        writeln!(output, "{}", entry)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use probe_rs::DebugProbeType;
+
+    fn stlink(serial: Option<&str>, identifier: &str, firmware: Option<&str>) -> ProbeEntry {
+        ProbeEntry {
+            info: DebugProbeInfo {
+                identifier: identifier.to_string(),
+                vendor_id: 0x0483,
+                product_id: 0x374B,
+                serial_number: serial.map(str::to_string),
+                probe_type: DebugProbeType::StLink,
+                hid_interface: None,
+            },
+            firmware: firmware.map(str::to_string),
+            in_use: false,
+        }
+    }
+
+    #[test]
+    fn entry_json_fields() {
+        let entry = stlink(Some("066DFF545150"), "STLink V2-1", Some("V2J37M26"));
+        assert_eq!(
+            entry.to_json(),
+            r#"{"type":"stlink","vid":"0483","pid":"374B","serial":"066DFF545150","identifier":"STLink V2-1","firmware":"V2J37M26","in_use":false}"#
+        );
+    }
+
+    #[test]
+    fn missing_serial_and_firmware_are_null() {
+        let mut entry = stlink(None, "STLink", None);
+        entry.in_use = true;
+        let json = entry.to_json();
+        assert!(json.contains(r#""serial":null,"#), "{}", json);
+        assert!(json.contains(r#""firmware":null,"in_use":true}"#), "{}", json);
+    }
+
+    #[test]
+    fn quotes_and_backslashes_escaped() {
+        assert_eq!(json_string(r#"bench "A""#), r#""bench \"A\"""#);
+        assert_eq!(json_string(r"\\?\usb#vid_0483"), r#""\\\\?\\usb#vid_0483""#);
+    }
+
+    #[test]
+    fn control_characters_escaped() {
+        assert_eq!(json_string("a\nb\tc\u{1}"), r#""a\u000ab\u0009c\u0001""#);
+        /* DEL and non-ASCII need no escape */
+        assert_eq!(json_string("\u{7f}µ"), "\"\u{7f}µ\"");
+    }
+
+    #[test]
+    fn json_array_of_entries() {
+        let entries = [stlink(Some("1"), "a", None), stlink(Some("2"), "b", None)];
+        let mut out = Vec::new();
+        write_inventory(&entries, true, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(r#"[{"type":"stlink""#) && out.ends_with("}]\n"), "{}", out);
+        assert_eq!(out.matches("},{").count(), 1, "{}", out);
+
+        let mut out = Vec::new();
+        write_inventory(&[], true, &mut out).unwrap();
+        assert_eq!(out, b"[]\n");
+    }
+}
@@ -0,0 +1,250 @@
+use super::*;
+
+use std::fmt;
+
+use probe_rs::{DebugProbeInfo, DebugProbeType, Probe};
+
+/// `PROBE_TYPES` - probe types `ProbeSelector` can select by name
+pub const PROBE_TYPES: [DebugProbeType; 4] = [
+    DebugProbeType::StLink,
+    DebugProbeType::CmsisDap,
+    DebugProbeType::JLink,
+    DebugProbeType::Ftdi,
+];
+
+pub fn probe_type_name(probe_type: &DebugProbeType) -> &'static str {
+    match probe_type {
+        DebugProbeType::StLink => "stlink",
+        DebugProbeType::CmsisDap => "cmsis-dap",
+        DebugProbeType::JLink => "jlink",
+        DebugProbeType::Ftdi => "ftdi",
+        DebugProbeType::EspJtag => "esp-jtag",
+        DebugProbeType::WchLink => "wch-link",
+    }
+}
+
+pub fn probe_type_from_name(name: &str) -> Option<DebugProbeType> {
+    PROBE_TYPES
+        .iter()
+        .find(|probe_type| probe_type_name(probe_type).eq_ignore_ascii_case(name))
+        .cloned()
+}
+
+/// `describe_probe` - one line of `list_probes`, e.g. `stlink 0483:374B SN 066DFF..., STLink V2-1`
+pub fn describe_probe(probe: &DebugProbeInfo) -> String {
+    format!(
+        "{} {:04X}:{:04X} SN {}, {}",
+        probe_type_name(&probe.probe_type),
+        probe.vendor_id,
+        probe.product_id,
+        probe.serial_number.as_deref().unwrap_or("?"),
+        probe.identifier
+    )
+}
+
+/// `list_probes` - `describe_probe` of all `probes`, for error messages
+pub fn list_probes(probes: &[DebugProbeInfo]) -> String {
+    if probes.is_empty() {
+        return "none".to_string();
+    }
+    probes.iter().map(describe_probe).collect::<Vec<_>>().join("; ")
+}
+
+/// `ProbeSelector` - which of the connected probes to open, all set fields must match
+///
+/// No field set selects the only ST-Link, as the bench setup always did.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProbeSelector {
+    pub probe_type: Option<DebugProbeType>,
+    pub vid_pid: Option<(u16, u16)>,
+    /// USB serial number, case insensitive
+    pub serial: Option<String>,
+    /// open an ST-Link whose firmware version can't be read, multi-AP access not checked
+    pub skip_firmware_check: bool,
+}
+
+impl ProbeSelector {
+    /// `parse_vid_pid` - `VID:PID` in hex, e.g. `0483:374B`
+    pub fn parse_vid_pid(value: &str) -> Option<(u16, u16)> {
+        let (vid, pid) = value.split_once(':')?;
+        let vid = u16::from_str_radix(vid.trim_start_matches("0x"), 16).ok()?;
+        let pid = u16::from_str_radix(pid.trim_start_matches("0x"), 16).ok()?;
+        Some((vid, pid))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.probe_type.is_none() && self.vid_pid.is_none() && self.serial.is_none()
+    }
+
+    pub fn matches(&self, probe: &DebugProbeInfo) -> bool {
+        let probe_type = match (&self.probe_type, self.is_empty()) {
+            (Some(probe_type), _) => Some(probe_type),
+            (None, true) => Some(&DebugProbeType::StLink),
+            (None, false) => None,
+        };
+        let type_ok = match probe_type {
+            Some(probe_type) => &probe.probe_type == probe_type,
+            None => true,
+        };
+        let vid_pid_ok = match self.vid_pid {
+            Some((vid, pid)) => probe.vendor_id == vid && probe.product_id == pid,
+            None => true,
+        };
+        let serial_ok = match (&self.serial, &probe.serial_number) {
+            (Some(serial), Some(number)) => number.eq_ignore_ascii_case(serial),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        type_ok && vid_pid_ok && serial_ok
+    }
+}
+
+impl fmt::Display for ProbeSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "stlink");
+        }
+        let mut parts = Vec::new();
+        if let Some(probe_type) = &self.probe_type {
+            parts.push(probe_type_name(probe_type).to_string());
+        }
+        if let Some((vid, pid)) = self.vid_pid {
+            parts.push(format!("{:04X}:{:04X}", vid, pid));
+        }
+        if let Some(serial) = &self.serial {
+            parts.push(format!("SN {}", serial));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// `select_probe` - the only probe of `probes` matching `selector`,
+/// `Error::ProbeNotFound` if none, `Error::ProbeAmbiguous` if more match
+pub fn select_probe<'a>(probes: &'a [DebugProbeInfo], selector: &ProbeSelector) -> Result<&'a DebugProbeInfo, Error> {
+    let matching: Vec<&DebugProbeInfo> = probes.iter().filter(|probe| selector.matches(probe)).collect();
+    match matching.as_slice() {
+        [probe] => Ok(probe),
+        [] => Err(Error::ProbeNotFound(format!("{}, connected: {}", selector, list_probes(probes)))),
+        _ => Err(Error::ProbeAmbiguous {
+            selection: selector.to_string(),
+            connected: list_probes(probes),
+        }),
+    }
+}
+
+/// `open_probe` - open the probe selected by `selector` of `Probe::list_all`
+///
+/// ST-Link firmware is checked for multi-AP access first, `Error::StLinkFirmwareTooOld` if it
+/// can't reach the MDM-AP, the version read error unless `selector.skip_firmware_check`.
+/// The version is read before open, the opened probe holds the USB interface.
+pub fn open_probe(selector: &ProbeSelector) -> Result<Probe, Error> {
+    let probes = Probe::list_all();
+    let info = select_probe(&probes, selector)?;
+    log::info!("probe: {}", describe_probe(info));
+    if is_stlink(info.vendor_id, info.product_id) {
+        match read_stlink_version(info.product_id, info.serial_number.as_deref()) {
+            Ok(version) => {
+                log::info!("ST-Link firmware {}", version);
+                version.check_multi_ap()?;
+            }
+            Err(err) if selector.skip_firmware_check => {
+                log::warn!("ST-Link firmware version not read, multi-AP access not checked: {}", report(&err))
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    info.open().map_err(|err| Error::ProbeOpen { source: err.into() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(probe_type: DebugProbeType, vid_pid: (u16, u16), serial: Option<&str>) -> DebugProbeInfo {
+        DebugProbeInfo {
+            identifier: probe_type_name(&probe_type).to_string(),
+            vendor_id: vid_pid.0,
+            product_id: vid_pid.1,
+            serial_number: serial.map(str::to_string),
+            probe_type,
+            hid_interface: None,
+        }
+    }
+
+    fn bench() -> Vec<DebugProbeInfo> {
+        vec![
+            probe(DebugProbeType::CmsisDap, (0x0D28, 0x0204), Some("0240000034")),
+            probe(DebugProbeType::StLink, (0x0483, 0x374B), Some("066DFF545150")),
+            probe(DebugProbeType::JLink, (0x1366, 0x0101), None),
+        ]
+    }
+
+    #[test]
+    fn vid_pid_parse() {
+        for (value, expected) in [
+            ("0483:374B", Some((0x0483, 0x374B))),
+            ("0x0483:0x374b", Some((0x0483, 0x374B))),
+            ("483:0x3748", Some((0x0483, 0x3748))),
+            ("0483", None),
+            ("0483:", None),
+            ("0x:374B", None),
+            ("10483:374B", None),
+            ("stlink:374B", None),
+        ] {
+            assert_eq!(ProbeSelector::parse_vid_pid(value), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn empty_selector_takes_the_stlink() {
+        let probes = bench();
+        let selected = select_probe(&probes, &ProbeSelector::default()).unwrap();
+        assert_eq!(selected.probe_type, DebugProbeType::StLink);
+    }
+
+    #[test]
+    fn other_field_drops_the_stlink_default() {
+        let probes = bench();
+        let selector = ProbeSelector { vid_pid: Some((0x0D28, 0x0204)), ..ProbeSelector::default() };
+        assert_eq!(select_probe(&probes, &selector).unwrap().probe_type, DebugProbeType::CmsisDap);
+        let selector = ProbeSelector { probe_type: Some(DebugProbeType::JLink), ..ProbeSelector::default() };
+        assert_eq!(select_probe(&probes, &selector).unwrap().vendor_id, 0x1366);
+    }
+
+    #[test]
+    fn serial_ignores_case() {
+        let probes = bench();
+        let selector = ProbeSelector { serial: Some("066dff545150".to_string()), ..ProbeSelector::default() };
+        assert!(selector.matches(&probes[1]));
+        assert!(!selector.matches(&probes[0]));
+        /* a probe without serial never matches a serial selection */
+        assert!(!selector.matches(&probes[2]));
+    }
+
+    #[test]
+    fn not_found_lists_connected() {
+        let probes = bench();
+        let selector = ProbeSelector { serial: Some("123".to_string()), ..ProbeSelector::default() };
+        match select_probe(&probes, &selector) {
+            Err(Error::ProbeNotFound(what)) => {
+                assert!(what.starts_with("SN 123, connected: "), "{}", what);
+                assert!(what.contains("stlink 0483:374B SN 066DFF545150"), "{}", what);
+                assert!(what.contains("jlink 1366:0101 SN ?"), "{}", what);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(select_probe(&[], &ProbeSelector::default()), Err(Error::ProbeNotFound(what)) if what.ends_with("none")));
+    }
+
+    #[test]
+    fn two_stlinks_are_ambiguous() {
+        let mut probes = bench();
+        probes.push(probe(DebugProbeType::StLink, (0x0483, 0x3748), Some("48FF6E066772")));
+        match select_probe(&probes, &ProbeSelector::default()) {
+            Err(Error::ProbeAmbiguous { selection, .. }) => assert_eq!(selection, "stlink"),
+            other => panic!("{:?}", other),
+        }
+        let selector = ProbeSelector { serial: Some("48ff6e066772".to_string()), ..ProbeSelector::default() };
+        assert_eq!(select_probe(&probes, &selector).unwrap().product_id, 0x3748);
+    }
+}
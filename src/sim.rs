@@ -0,0 +1,672 @@
+use super::*;
+
+use std::collections::HashMap;
+
+use super::connect::{AIRCR_ADDRESS, AIRCR_SYSRESETREQ, AIRCR_VECTKEY};
+
+/// ARMv6-M `DHCSR` bits used by the model
+const DHCSR_ADDRESS: u64 = 0xE000_EDF0;
+const DHCSR_DBGKEY: u32 = 0xA05F_0000;
+const DHCSR_C_DEBUGEN: u32 = 1 << 0;
+const DHCSR_C_HALT: u32 = 1 << 1;
+const DHCSR_S_HALT: u32 = 1 << 17;
+const DHCSR_S_RESET_ST: u32 = 1 << 25;
+
+/// `SIM_FLASH_READY_DELAY` - accesses after reset assert before Flash Ready is set
+pub const SIM_FLASH_READY_DELAY: u32 = 3;
+/// `SIM_DP_IDCODE` - SW-DP of Cortex-M0+
+pub const SIM_DP_IDCODE: u32 = 0x0BC1_1477;
+/// `SIM_AHB_AP_IDR` - AHB-AP of Cortex-M0+, at AP 0
+pub const SIM_AHB_AP_IDR: u32 = 0x0477_0031;
+/// `SIM_SDID` / `SIM_FCFG1` / `SIM_UID` - KE14Z256, 64 pins, 32 KB SRAM
+pub const SIM_SDID: u32 = 0x1416_0005;
+pub const SIM_FCFG1: u32 = 0x0900_0000;
+pub const SIM_UID: [u32; 4] = [0x0000_0011, 0x2233_4455, 0x6677_8899, 0xAABB_CCDD];
+/// `SIM_INITIAL_SP` / `SIM_RESET_HANDLER` - vector table of the simulated firmware
+pub const SIM_INITIAL_SP: u32 = 0x2000_1800;
+pub const SIM_RESET_HANDLER: u32 = 0x0000_0411;
+/// `SIM_FIRMWARE_PC` - PC of a core halted while running firmware
+pub const SIM_FIRMWARE_PC: u32 = 0x0000_0536;
+/// `SIM_MASS_ERASE_DURATION` - accesses after mass erase start before the control bit self-clears
+pub const SIM_MASS_ERASE_DURATION: u32 = 5;
+
+/// `SimScenario` - scripted target behaviour for `SimTarget`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SimScenario {
+    /// unsecured part, all requests accepted
+    Normal,
+    /// System Security set, mass erase enabled
+    Secured,
+    /// System Security set, mass erase disabled by FSEC[MEEN]
+    SecuredMassEraseDisabled,
+    /// Flash Ready is never set
+    FlashNeverReady,
+    /// System Reset Request is ignored, System Reset status never goes low
+    ResetNeverAsserts,
+    /// firmware enters STOP right after boot, before the halt is taken, for the first 3 resets
+    LowPowerAfterBoot,
+    /// MDM-AP Debug Disable set at start, can be cleared
+    DebugDisabled,
+    /// MDM-AP Debug Disable set and stuck, writes can't clear it
+    DebugDisableLocked,
+    /// next 2 AP accesses get FAULT ack and set DP STICKYERR
+    ApFaults,
+    /// AHB-AP only, no Kinetis MDM-AP - some other Cortex-M part
+    NotKinetis,
+}
+
+impl SimScenario {
+    pub const ALL: [SimScenario; 10] = [
+        SimScenario::Normal,
+        SimScenario::Secured,
+        SimScenario::SecuredMassEraseDisabled,
+        SimScenario::FlashNeverReady,
+        SimScenario::ResetNeverAsserts,
+        SimScenario::LowPowerAfterBoot,
+        SimScenario::DebugDisabled,
+        SimScenario::DebugDisableLocked,
+        SimScenario::ApFaults,
+        SimScenario::NotKinetis,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SimScenario::Normal => "normal",
+            SimScenario::Secured => "secured",
+            SimScenario::SecuredMassEraseDisabled => "secured-no-mass-erase",
+            SimScenario::FlashNeverReady => "flash-never-ready",
+            SimScenario::ResetNeverAsserts => "reset-never-asserts",
+            SimScenario::LowPowerAfterBoot => "low-power-after-boot",
+            SimScenario::DebugDisabled => "debug-disabled",
+            SimScenario::DebugDisableLocked => "debug-disable-locked",
+            SimScenario::ApFaults => "ap-faults",
+            SimScenario::NotKinetis => "not-kinetis",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|scenario| scenario.name() == name)
+    }
+}
+
+/// `SimTarget` - software model of KE1xZ MDM-AP and core debug halt
+///
+/// Time is counted in accesses, not wall clock: every AP or memory access is one tick,
+/// so flash-ready and mass erase delays are deterministic.
+///
+/// Public fields are the knobs of the model, `SimTarget::new` sets them from a `SimScenario`.
+#[derive(Debug, Clone)]
+pub struct SimTarget {
+    /// value returned by MDM-AP IDR
+    pub idr: u32,
+    /// AccessPort index of MDM-AP, `None` - no MDM-AP
+    pub mdm_ap_port: Option<u8>,
+    /// IDR of the AHB-AP at AP 0
+    pub mem_ap_idr: u32,
+    /// System Security
+    pub secured: bool,
+    /// FSEC[MEEN], mass erase by MDM-AP allowed
+    pub mass_erase_enable: bool,
+    /// FSEC[KEYEN]
+    pub backdoor_key_enable: bool,
+    /// `None` - Flash Ready never set
+    pub flash_ready_delay: Option<u32>,
+    pub mass_erase_duration: u32,
+    /// `false` - System Reset Request is ignored
+    pub reset_asserts: bool,
+    /// number of next reset releases where firmware enters STOP before the halt is taken
+    pub low_power_escapes: u32,
+    /// Debug Disable can't be cleared
+    pub debug_disable_locked: bool,
+    /// value returned by DP IDCODE
+    pub dp_idcode: u32,
+    /// number of next AP / memory accesses answered with FAULT
+    pub ap_faults: u32,
+    /// number of next MDM-AP Control writes acked but not latched
+    pub control_write_drops: u32,
+    /// MDM-AP Control bits which latch, `DeviceFamily::mdm_control_bits`
+    pub control_bits: u32,
+    dp_ctrl_stat: u32,
+    control: u32,
+    control_writes: u32,
+    nrst: bool,
+    in_reset: bool,
+    core_held: bool,
+    halted: bool,
+    /// core in STOP, debug memory access blocked
+    stopped: bool,
+    mass_erase_ack: bool,
+    erase_remaining: Option<u32>,
+    flash_ready_in: Option<u32>,
+    dhcsr: u32,
+    demcr: u32,
+    /// `S_RESET_ST`, cleared by `DHCSR` read
+    reset_seen: bool,
+    dcrdr: u32,
+    pc: u32,
+    sp: u32,
+    memory: HashMap<u64, u32>,
+    accesses: u64,
+}
+
+impl SimTarget {
+    pub fn new(scenario: SimScenario) -> Self {
+        let mut sim = SimTarget {
+            idr: IDR_REG_CHECK_VALUE,
+            mdm_ap_port: Some(MKE_MDM_AP_PORT),
+            mem_ap_idr: SIM_AHB_AP_IDR,
+            secured: false,
+            mass_erase_enable: true,
+            backdoor_key_enable: false,
+            flash_ready_delay: Some(SIM_FLASH_READY_DELAY),
+            mass_erase_duration: SIM_MASS_ERASE_DURATION,
+            reset_asserts: true,
+            low_power_escapes: 0,
+            debug_disable_locked: false,
+            dp_idcode: SIM_DP_IDCODE,
+            ap_faults: 0,
+            control_write_drops: 0,
+            control_bits: MDM_CONTROL_NO_VLLS,
+            dp_ctrl_stat: 0,
+            control: 0,
+            control_writes: 0,
+            nrst: false,
+            in_reset: false,
+            core_held: false,
+            halted: false,
+            stopped: false,
+            mass_erase_ack: false,
+            erase_remaining: None,
+            flash_ready_in: Some(0),
+            dhcsr: 0,
+            demcr: 0,
+            reset_seen: false,
+            dcrdr: 0,
+            pc: SIM_FIRMWARE_PC,
+            sp: SIM_INITIAL_SP - 0x40,
+            memory: HashMap::from([
+                (VECTOR_TABLE_ADDRESS, SIM_INITIAL_SP),
+                (VECTOR_TABLE_ADDRESS + 4, SIM_RESET_HANDLER),
+                (SIM_SDID_ADDRESS, SIM_SDID),
+                (SIM_FCFG1_ADDRESS, SIM_FCFG1),
+                (SIM_UIDH_ADDRESS, SIM_UID[0]),
+                (SIM_UIDMH_ADDRESS, SIM_UID[1]),
+                (SIM_UIDML_ADDRESS, SIM_UID[2]),
+                (SIM_UIDL_ADDRESS, SIM_UID[3]),
+            ]),
+            accesses: 0,
+        };
+        match scenario {
+            SimScenario::Normal => {}
+            SimScenario::Secured => sim.secured = true,
+            SimScenario::SecuredMassEraseDisabled => {
+                sim.secured = true;
+                sim.mass_erase_enable = false;
+            }
+            SimScenario::FlashNeverReady => {
+                sim.flash_ready_delay = None;
+                sim.flash_ready_in = None;
+            }
+            SimScenario::ResetNeverAsserts => sim.reset_asserts = false,
+            SimScenario::LowPowerAfterBoot => sim.low_power_escapes = 3,
+            SimScenario::DebugDisabled => sim.control = MKE_MDM_CONTROL_DBG_DIS_BIT,
+            SimScenario::DebugDisableLocked => {
+                sim.control = MKE_MDM_CONTROL_DBG_DIS_BIT;
+                sim.debug_disable_locked = true;
+            }
+            SimScenario::ApFaults => sim.ap_faults = 2,
+            SimScenario::NotKinetis => sim.mdm_ap_port = None,
+        }
+        sim
+    }
+
+    /// `accesses` - number of AP and memory accesses done so far
+    pub fn accesses(&self) -> u64 {
+        self.accesses
+    }
+
+    /// `control_writes` - number of MDM-AP Control writes done so far, dropped ones included
+    pub fn control_writes(&self) -> u32 {
+        self.control_writes
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn is_in_reset(&self) -> bool {
+        self.in_reset
+    }
+
+    fn tick(&mut self) {
+        self.accesses += 1;
+        if let Some(remaining) = self.flash_ready_in {
+            self.flash_ready_in = Some(remaining.saturating_sub(1));
+        }
+        match self.erase_remaining {
+            Some(0) => {
+                self.erase_remaining = None;
+                self.control &= !MKE_MDM_CONTROL_FLASH_MASS_ERASE_BIT;
+                self.secured = false;
+                self.memory.retain(|address, _| *address >= 0x2000_0000);
+            }
+            Some(remaining) => self.erase_remaining = Some(remaining - 1),
+            None => {}
+        }
+    }
+
+    fn flash_ready(&self) -> bool {
+        self.flash_ready_in == Some(0) && self.erase_remaining.is_none()
+    }
+
+    fn halt_requested(&self) -> bool {
+        let dhcsr_halt = self.dhcsr & (DHCSR_C_DEBUGEN | DHCSR_C_HALT)
+            == DHCSR_C_DEBUGEN | DHCSR_C_HALT;
+        let dbg_req = self.control & MKE_MDM_CONTROL_DBG_REQ_BIT != 0;
+        let dbg_dis = self.control & MKE_MDM_CONTROL_DBG_DIS_BIT != 0;
+        !self.secured && !self.stopped && !dbg_dis && (dhcsr_halt || dbg_req)
+    }
+
+    /// core leaves reset / core hold, halt at once if requested
+    fn update_core(&mut self) {
+        if !self.in_reset && !self.core_held && self.halt_requested() {
+            self.halted = true;
+        }
+    }
+
+    fn status(&self) -> u32 {
+        let mut status = 0;
+        if self.mass_erase_ack {
+            status |= MKE_MDM_STATUS_FLASH_MASS_ERASE_ACK_BIT;
+        }
+        if self.flash_ready() {
+            status |= MKE_MDM_STATUS_FLASH_READY_BIT;
+        }
+        if self.secured {
+            status |= MKE_MDM_STATUS_SYSTEM_SECURITY_BIT;
+        }
+        if !self.in_reset {
+            status |= MKE_MDM_STATUS_SYSTEM_RESET_BIT;
+        }
+        if self.mass_erase_enable {
+            status |= MKE_MDM_STATUS_MASS_ERASE_ENABLE_BIT;
+        }
+        if self.backdoor_key_enable {
+            status |= MKE_MDM_STATUS_BACKDOOR_KEY_ENABLE_BIT;
+        }
+        if self.halted {
+            status |= MKE_MDM_STATUS_CORE_HALTED_BIT;
+        }
+        if self.stopped {
+            status |= MKE_MDM_STATUS_LP_ENABLED_BIT | MKE_MDM_STATUS_CORE_SLEEPDEEP_BIT;
+        }
+        status
+    }
+
+    fn assert_reset(&mut self) {
+        self.in_reset = true;
+        self.reset_seen = true;
+        self.stopped = false;
+        self.halted = false;
+        self.flash_ready_in = self.flash_ready_delay;
+    }
+
+    /// core held on reset release if Core Hold Reset is set,
+    /// a core not halted at the reset vector runs firmware
+    fn release_reset(&mut self) {
+        if !self.in_reset {
+            return;
+        }
+        self.in_reset = false;
+        self.core_held = self.control & MKE_MDM_CONTROL_CORE_HOLD_BIT != 0;
+        self.sp = self.memory.get(&VECTOR_TABLE_ADDRESS).copied().unwrap_or(0xFFFF_FFFF);
+        self.pc = self.memory.get(&(VECTOR_TABLE_ADDRESS + 4)).copied().unwrap_or(0xFFFF_FFFF) & !1;
+        self.start_core();
+    }
+
+    /// core starts at the reset vector, unless still held
+    fn start_core(&mut self) {
+        if self.core_held {
+            return;
+        }
+        if self.low_power_escapes > 0 {
+            self.low_power_escapes -= 1;
+            self.stopped = true;
+            self.pc = SIM_FIRMWARE_PC;
+            return;
+        }
+        let vector_catch = self.demcr & DEMCR_VC_CORERESET != 0 && self.dhcsr & DHCSR_C_DEBUGEN != 0;
+        if vector_catch && !self.secured {
+            self.halted = true;
+        }
+        self.update_core();
+        if !self.halted {
+            self.pc = SIM_FIRMWARE_PC;
+            self.sp = SIM_INITIAL_SP - 0x40;
+        }
+    }
+
+    fn write_control(&mut self, value: u32) {
+        self.control_writes += 1;
+        if self.control_write_drops > 0 {
+            self.control_write_drops -= 1;
+            return;
+        }
+        let old = self.control;
+        self.control = value & self.control_bits;
+        if self.debug_disable_locked {
+            self.control |= MKE_MDM_CONTROL_DBG_DIS_BIT;
+        }
+        let rising = self.control & !old;
+        let falling = old & !self.control;
+
+        if rising & MKE_MDM_CONTROL_SYS_RESET_BIT != 0 && self.reset_asserts {
+            self.assert_reset();
+        }
+        if falling & MKE_MDM_CONTROL_SYS_RESET_BIT != 0 && !self.nrst {
+            self.release_reset();
+        }
+        if falling & MKE_MDM_CONTROL_CORE_HOLD_BIT != 0 && self.core_held {
+            self.core_held = false;
+            self.start_core();
+        }
+        if rising & MKE_MDM_CONTROL_FLASH_MASS_ERASE_BIT != 0 {
+            if self.mass_erase_enable && self.flash_ready() {
+                self.mass_erase_ack = true;
+                self.erase_remaining = Some(self.mass_erase_duration);
+            } else {
+                self.control &= !MKE_MDM_CONTROL_FLASH_MASS_ERASE_BIT;
+            }
+        }
+        self.update_core();
+    }
+
+    /// `DCRSR` write, only SP and PC are modelled
+    fn core_register(&mut self, dcrsr: u32) {
+        let write = dcrsr & DCRSR_REGWNR != 0;
+        let register = match dcrsr & 0x1F {
+            CORE_REG_SP => &mut self.sp,
+            CORE_REG_PC => &mut self.pc,
+            _ => {
+                if !write {
+                    self.dcrdr = 0;
+                }
+                return;
+            }
+        };
+        if write {
+            *register = self.dcrdr;
+        } else {
+            self.dcrdr = *register;
+        }
+    }
+
+    /// AP access is answered with FAULT while STICKYERR is set or a fault is scripted
+    fn ap_fault(&mut self) -> Result<(), BoxError> {
+        if self.dp_ctrl_stat & DP_CTRL_STAT_STICKYERR != 0 {
+            return Err("sim: FAULT ack, STICKYERR set".into());
+        }
+        if self.ap_faults > 0 {
+            self.ap_faults -= 1;
+            self.dp_ctrl_stat |= DP_CTRL_STAT_STICKYERR;
+            return Err("sim: FAULT ack".into());
+        }
+        Ok(())
+    }
+
+    fn memory_access_allowed(&self) -> Result<(), BoxError> {
+        if self.secured {
+            return Err("sim: memory access blocked, target secured".into());
+        }
+        if self.stopped {
+            return Err("sim: memory access blocked, core in STOP".into());
+        }
+        Ok(())
+    }
+}
+
+impl Transport for SimTarget {
+    fn read_dp_register(&mut self, address: u8) -> Result<u32, Error> {
+        match address {
+            DP_IDCODE => Ok(self.dp_idcode),
+            DP_CTRL_STAT => Ok(self.dp_ctrl_stat),
+            _ => Ok(0),
+        }
+    }
+
+    fn write_dp_register(&mut self, address: u8, value: u32) -> Result<(), Error> {
+        match address {
+            DP_ABORT => {
+                let mut clear = 0;
+                if value & DP_ABORT_STKERRCLR != 0 {
+                    clear |= DP_CTRL_STAT_STICKYERR;
+                }
+                if value & DP_ABORT_ORUNERRCLR != 0 {
+                    clear |= DP_CTRL_STAT_STICKYORUN;
+                }
+                if value & DP_ABORT_WDERRCLR != 0 {
+                    clear |= DP_CTRL_STAT_WDATAERR;
+                }
+                if value & DP_ABORT_STKCMPCLR != 0 {
+                    clear |= DP_CTRL_STAT_STICKYCMP;
+                }
+                self.dp_ctrl_stat &= !clear;
+            }
+            DP_CTRL_STAT => {
+                /* power-up acknowledged at once */
+                let requests = value & (DP_CTRL_STAT_CDBGPWRUPREQ | DP_CTRL_STAT_CSYSPWRUPREQ);
+                self.dp_ctrl_stat = (self.dp_ctrl_stat & 0x0FFF_FFFF) | requests | (requests << 1);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn read_ap_register(&mut self, ap: u8, address: u8) -> Result<u32, Error> {
+        self.tick();
+        self.ap_fault().map_err(|source| Error::ApRead { ap, address, source })?;
+        if ap == 0 && address == AP_IDR {
+            return Ok(self.mem_ap_idr);
+        }
+        if Some(ap) != self.mdm_ap_port {
+            return Ok(0);
+        }
+        match address {
+            MKE_MDM_STATUS => Ok(self.status()),
+            MKE_MDM_CONTROL => Ok(self.control),
+            MKE_MDM_IDR_REG => Ok(self.idr),
+            _ => Ok(0),
+        }
+    }
+
+    fn write_ap_register(&mut self, ap: u8, address: u8, value: u32) -> Result<(), Error> {
+        self.tick();
+        self.ap_fault().map_err(|source| Error::ApWrite { ap, address, value, source })?;
+        if Some(ap) == self.mdm_ap_port && address == MKE_MDM_CONTROL {
+            self.write_control(value);
+        }
+        Ok(())
+    }
+
+    fn read_mem_word(&mut self, ap: u8, address: u64) -> Result<u32, Error> {
+        self.tick();
+        self.ap_fault().map_err(|source| Error::MemRead { ap, address, source })?;
+        self.memory_access_allowed()
+            .map_err(|source| Error::MemRead { ap, address, source })?;
+        if address == DHCSR_ADDRESS {
+            let mut dhcsr = self.dhcsr;
+            if self.halted {
+                dhcsr |= DHCSR_S_HALT | DHCSR_S_REGRDY;
+            }
+            if self.reset_seen {
+                dhcsr |= DHCSR_S_RESET_ST;
+                self.reset_seen = false;
+            }
+            return Ok(dhcsr);
+        }
+        match address {
+            DEMCR_ADDRESS => return Ok(self.demcr),
+            DCRDR_ADDRESS => return Ok(self.dcrdr),
+            _ => {}
+        }
+        Ok(self.memory.get(&address).copied().unwrap_or(0xFFFF_FFFF))
+    }
+
+    fn write_mem_word(&mut self, ap: u8, address: u64, value: u32) -> Result<(), Error> {
+        self.tick();
+        self.ap_fault().map_err(|source| Error::MemWrite { ap, address, value, source })?;
+        self.memory_access_allowed().map_err(|source| Error::MemWrite {
+            ap,
+            address,
+            value,
+            source,
+        })?;
+        if address == DHCSR_ADDRESS {
+            if value & 0xFFFF_0000 == DHCSR_DBGKEY {
+                self.dhcsr = value & 0xFFFF;
+                if self.dhcsr & DHCSR_C_HALT == 0 {
+                    self.halted = false;
+                }
+                self.update_core();
+            }
+            return Ok(());
+        }
+        if address == DEMCR_ADDRESS {
+            self.demcr = value;
+            return Ok(());
+        }
+        if address == DCRDR_ADDRESS {
+            self.dcrdr = value;
+            return Ok(());
+        }
+        if address == DCRSR_ADDRESS {
+            if self.halted {
+                self.core_register(value);
+            }
+            return Ok(());
+        }
+        if address == AIRCR_ADDRESS {
+            if value == AIRCR_VECTKEY | AIRCR_SYSRESETREQ {
+                self.assert_reset();
+                self.release_reset();
+            }
+            return Ok(());
+        }
+        self.memory.insert(address, value);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn set_nrst(&mut self, asserted: bool) -> Result<(), Error> {
+        self.tick();
+        if asserted && !self.nrst {
+            self.assert_reset();
+        }
+        if !asserted && self.nrst {
+            self.nrst = false;
+            if self.control & MKE_MDM_CONTROL_SYS_RESET_BIT == 0 {
+                self.release_reset();
+            }
+        }
+        self.nrst = asserted;
+        Ok(())
+    }
+}
+
+/// `connect_sim` - `dp_health_check` and `connect` over `RetryTransport`, as done with a probe
+pub fn connect_sim(mut sim: SimTarget, options: &ConnectOptions) -> Result<Connection, Error> {
+    if options.strategy == ConnectStrategy::HardwareReset {
+        /* as `open_transport` does for the probe */
+        sim.set_nrst(true)?;
+    }
+    let mut sim = RetryTransport::new(sim, AP_ACCESS_RETRIES);
+    dp_health_check(&mut sim)?;
+    connect(&mut sim, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_strategy(strategy: ConnectStrategy) -> ConnectOptions {
+        ConnectOptions { strategy, ..ConnectOptions::default() }
+    }
+
+    #[test]
+    fn scenarios_connect_result() {
+        let options = ConnectOptions::default();
+        for scenario in SimScenario::ALL {
+            let result = connect_sim(SimTarget::new(scenario), &options);
+            let expected = match scenario {
+                SimScenario::Normal | SimScenario::DebugDisabled | SimScenario::ApFaults => match &result {
+                    Ok(connection) => {
+                        connection.device.part_number() == "KE14Z256"
+                            && connection.core_state.halted
+                            && connection.reset_halt.is_none()
+                    }
+                    Err(_) => false,
+                },
+                SimScenario::Secured | SimScenario::SecuredMassEraseDisabled => {
+                    matches!(result, Err(Error::TargetSecured))
+                }
+                SimScenario::FlashNeverReady => {
+                    matches!(result, Err(Error::Timeout { operation: "flash ready", .. }))
+                }
+                SimScenario::ResetNeverAsserts => {
+                    matches!(result, Err(Error::Timeout { operation: "system reset assert", .. }))
+                }
+                SimScenario::LowPowerAfterBoot => {
+                    matches!(result, Err(Error::MemRead { ap: 0, address: 0xE000_EDF0, .. }))
+                }
+                SimScenario::DebugDisableLocked => matches!(result, Err(Error::DebugDisabled { .. })),
+                SimScenario::NotKinetis => matches!(result, Err(Error::UnexpectedApTopology { .. })),
+            };
+            assert!(expected, "{}: {:?}", scenario.name(), result);
+        }
+    }
+
+    #[test]
+    fn secured_mass_erase() {
+        let options = ConnectOptions { mass_erase: true, ..ConnectOptions::default() };
+        let result = connect_sim(SimTarget::new(SimScenario::Secured), &options);
+        assert!(matches!(&result, Ok(connection) if connection.core_state.halted), "{:?}", result);
+        let result = connect_sim(SimTarget::new(SimScenario::SecuredMassEraseDisabled), &options);
+        assert!(matches!(result, Err(Error::MassEraseDisabled)), "{:?}", result);
+    }
+
+    #[test]
+    fn low_power_wake_and_catch() {
+        let result = connect_sim(SimTarget::new(SimScenario::LowPowerAfterBoot), &with_strategy(ConnectStrategy::WakeAndCatch));
+        assert!(matches!(&result, Ok(connection) if connection.core_state.halted), "{:?}", result);
+    }
+
+    #[test]
+    fn core_hold_secured() {
+        let result = connect_sim(SimTarget::new(SimScenario::Secured), &with_strategy(ConnectStrategy::CoreHoldRelease));
+        assert!(matches!(result, Err(Error::TargetSecured)), "{:?}", result);
+    }
+
+    #[test]
+    fn vector_catch_reset_halt() {
+        for strategy in [
+            ConnectStrategy::An4835MdmReset,
+            ConnectStrategy::HardwareReset,
+            ConnectStrategy::SoftwareReset,
+            ConnectStrategy::CoreHoldRelease,
+        ] {
+            let options = ConnectOptions { vector_catch: true, ..with_strategy(strategy) };
+            let result = connect_sim(SimTarget::new(SimScenario::Normal), &options);
+            let reset_halt = result.as_ref().ok().and_then(|connection| connection.reset_halt);
+            assert_eq!(
+                reset_halt.map(|halt| (halt.pc, halt.sp)),
+                Some((SIM_RESET_HANDLER & !1, SIM_INITIAL_SP)),
+                "{}: {:?}",
+                strategy.name(),
+                result
+            );
+        }
+    }
+}
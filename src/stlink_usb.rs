@@ -0,0 +1,215 @@
+use super::*;
+
+use std::fmt;
+
+/// `STLINK_VID` - ST-Link USB vendor ID
+pub const STLINK_VID: u16 = 0x0483;
+/// ST-Link USB product IDs
+pub const STLINK_V2_PID: u16 = 0x3748;
+pub const STLINK_V2_1_PIDS: [u16; 3] = [0x374A, 0x374B, 0x3752];
+pub const STLINK_V3_PIDS: [u16; 5] = [0x374E, 0x374F, 0x3753, 0x3754, 0x3757];
+
+/// ST-Link bulk endpoints, V2 sends commands on EP2, V2-1 and V3 on EP1
+const STLINK_V2_EP_OUT: u8 = 0x02;
+const STLINK_EP_OUT: u8 = 0x01;
+const STLINK_EP_IN: u8 = 0x81;
+
+/// ST-Link commands, 16 byte command block
+const STLINK_GET_VERSION: u8 = 0xF1;
+const STLINK_GET_VERSION_EX: u8 = 0xFB;
+const STLINK_CMD_SIZE: usize = 16;
+
+/// `STLINK_USB_TIMEOUT_MS` - timeout of one bulk transfer
+pub const STLINK_USB_TIMEOUT_MS: u64 = 1000;
+
+/// `STLINK_V2_MIN_JTAG_MULTI_AP` - first V2 JTAG firmware with access to AP other than AP 0, V3 has it always
+pub const STLINK_V2_MIN_JTAG_MULTI_AP: u8 = 28;
+
+/// `StLinkVersion` - ST-Link firmware version, printed as ST does, e.g. `V2J37S7`, `V3J7M3B5S1`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StLinkVersion {
+    /// hardware generation, 2 or 3
+    pub stlink: u8,
+    /// JTAG / SWD firmware version
+    pub jtag: u8,
+    /// SWIM firmware version, on V2-1 the mass storage version
+    pub swim: u8,
+    /// V3 only, mass storage and bridge firmware versions
+    pub msd: Option<u8>,
+    pub bridge: Option<u8>,
+}
+
+impl StLinkVersion {
+    /// `parse_version` - 6 byte reply of `GET_VERSION`: `[15:12]` ST-Link, `[11:6]` JTAG, `[5:0]` SWIM
+    pub fn parse_version(reply: &[u8; 6]) -> Self {
+        let version = u16::from_be_bytes([reply[0], reply[1]]);
+        Self {
+            stlink: ((version >> 12) & 0xF) as u8,
+            jtag: ((version >> 6) & 0x3F) as u8,
+            swim: (version & 0x3F) as u8,
+            msd: None,
+            bridge: None,
+        }
+    }
+
+    /// `parse_version_ex` - 12 byte reply of V3 `GET_VERSION_EX`
+    pub fn parse_version_ex(reply: &[u8; 12]) -> Self {
+        Self {
+            stlink: reply[0],
+            swim: reply[1],
+            jtag: reply[2],
+            msd: Some(reply[3]),
+            bridge: Some(reply[4]),
+        }
+    }
+
+    /// `supports_multi_ap` - raw AP access beyond AP 0, needed for the MDM-AP
+    pub fn supports_multi_ap(&self) -> bool {
+        self.stlink >= 3 || self.jtag >= STLINK_V2_MIN_JTAG_MULTI_AP
+    }
+
+    /// `check_multi_ap` - `Error::StLinkFirmwareTooOld` if the MDM-AP can't be reached by this firmware
+    pub fn check_multi_ap(&self) -> Result<(), Error> {
+        if !self.supports_multi_ap() {
+            return Err(Error::StLinkFirmwareTooOld {
+                found: self.to_string(),
+                required: format!("V{}J{}", self.stlink, STLINK_V2_MIN_JTAG_MULTI_AP),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for StLinkVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "V{}J{}", self.stlink, self.jtag)?;
+        if let Some(msd) = self.msd {
+            write!(f, "M{}", msd)?;
+        }
+        if let Some(bridge) = self.bridge {
+            write!(f, "B{}", bridge)?;
+        }
+        write!(f, "S{}", self.swim)
+    }
+}
+
+pub fn is_stlink(vendor_id: u16, product_id: u16) -> bool {
+    vendor_id == STLINK_VID
+        && (product_id == STLINK_V2_PID || STLINK_V2_1_PIDS.contains(&product_id) || STLINK_V3_PIDS.contains(&product_id))
+}
+
+fn usb_error(operation: &'static str) -> impl FnOnce(rusb::Error) -> Error {
+    move |err| Error::Usb { operation, source: err }
+}
+
+/// `serial_matches` - `serial` as listed by probe-rs, ASCII or hex of the raw bytes (old V2 firmware)
+fn serial_matches(usb_serial: &str, serial: &str) -> bool {
+    let hex: String = usb_serial.bytes().map(|byte| format!("{:02X}", byte)).collect();
+    usb_serial.eq_ignore_ascii_case(serial) || hex.eq_ignore_ascii_case(serial)
+}
+
+/// `read_stlink_version` - firmware version of ST-Link `product_id` / `serial` by `GET_VERSION`,
+/// V3 by `GET_VERSION_EX`. The probe must not be opened by anyone else, other ST-Links which
+/// can't be opened are skipped.
+pub fn read_stlink_version(product_id: u16, serial: Option<&str>) -> Result<StLinkVersion, Error> {
+    let devices = rusb::devices().map_err(usb_error("list USB devices"))?;
+    let mut handle = None;
+    /* an ST-Link in use by another program can't be opened, it may not be the selected one */
+    let mut open_error = None;
+    for device in devices.iter() {
+        let Ok(descriptor) = device.device_descriptor() else { continue };
+        if descriptor.vendor_id() != STLINK_VID || descriptor.product_id() != product_id {
+            continue;
+        }
+        let device_handle = match device.open() {
+            Ok(device_handle) => device_handle,
+            Err(err) => {
+                log::debug!("ST-Link on bus {} address {} not opened: {}", device.bus_number(), device.address(), err);
+                open_error = Some(err);
+                continue;
+            }
+        };
+        if let Some(serial) = serial {
+            let usb_serial = device_handle.read_serial_number_string_ascii(&descriptor).unwrap_or_default();
+            if !serial_matches(&usb_serial, serial) {
+                continue;
+            }
+        }
+        handle = Some(device_handle);
+        break;
+    }
+    let mut handle = match (handle, open_error) {
+        (Some(handle), _) => handle,
+        /* the selected ST-Link may be the one which failed to open */
+        (None, Some(err)) => return Err(usb_error("open ST-Link")(err)),
+        (None, None) => {
+            return Err(Error::ProbeNotFound(format!(
+                "ST-Link {:04X}:{:04X} SN {}",
+                STLINK_VID,
+                product_id,
+                serial.unwrap_or("?")
+            )))
+        }
+    };
+
+    handle.claim_interface(0).map_err(usb_error("claim ST-Link interface"))?;
+    let ep_out = if product_id == STLINK_V2_PID { STLINK_V2_EP_OUT } else { STLINK_EP_OUT };
+    let timeout = time::Duration::from_millis(STLINK_USB_TIMEOUT_MS);
+    let v3 = STLINK_V3_PIDS.contains(&product_id);
+
+    let mut command = [0u8; STLINK_CMD_SIZE];
+    command[0] = if v3 { STLINK_GET_VERSION_EX } else { STLINK_GET_VERSION };
+    handle.write_bulk(ep_out, &command, timeout).map_err(usb_error("ST-Link GET_VERSION"))?;
+    let version = if v3 {
+        let mut reply = [0u8; 12];
+        handle.read_bulk(STLINK_EP_IN, &mut reply, timeout).map_err(usb_error("ST-Link GET_VERSION_EX reply"))?;
+        StLinkVersion::parse_version_ex(&reply)
+    } else {
+        let mut reply = [0u8; 6];
+        handle.read_bulk(STLINK_EP_IN, &mut reply, timeout).map_err(usb_error("ST-Link GET_VERSION reply"))?;
+        StLinkVersion::parse_version(&reply)
+    };
+    let _ = handle.release_interface(0);
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v2_version_with_multi_ap() {
+        let version = StLinkVersion::parse_version(&[0x29, 0x47, 0x83, 0x04, 0x48, 0x37]);
+        assert_eq!((version.stlink, version.jtag, version.swim), (2, 37, 7));
+        assert_eq!(version.to_string(), "V2J37S7");
+        assert!(version.check_multi_ap().is_ok());
+    }
+
+    #[test]
+    fn v2_firmware_before_j28_too_old() {
+        let version = StLinkVersion::parse_version(&[0x26, 0xC6, 0x83, 0x04, 0x48, 0x37]);
+        match version.check_multi_ap() {
+            Err(Error::StLinkFirmwareTooOld { found, required }) => {
+                assert_eq!(found, "V2J27S6");
+                assert_eq!(required, "V2J28");
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn v3_version_ex() {
+        let version = StLinkVersion::parse_version_ex(&[3, 1, 7, 3, 5, 0, 0x83, 0x04, 0x4F, 0x37, 0, 0]);
+        assert_eq!(version.to_string(), "V3J7M3B5S1");
+        /* any V3 firmware reaches the MDM-AP, J7 < J28 does not matter */
+        assert!(version.supports_multi_ap());
+    }
+
+    #[test]
+    fn stlink_usb_ids() {
+        assert!(is_stlink(STLINK_VID, STLINK_V2_PID));
+        assert!(is_stlink(0x0483, 0x374B) && is_stlink(0x0483, 0x3754));
+        assert!(!is_stlink(0x0483, 0x5740));
+        assert!(!is_stlink(0x0D28, 0x374B));
+    }
+}
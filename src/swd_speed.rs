@@ -0,0 +1,120 @@
+use super::*;
+
+use probe_rs::Probe;
+
+/// `SWD_SPEEDS_KHZ` - clock steps of `SwdSpeed::Auto`, fastest first, all supported by ST-Link V2
+pub const SWD_SPEEDS_KHZ: [u32; 7] = [4000, 1800, 950, 480, 240, 100, 50];
+/// `SWD_SPEED_CHECK_READS` - MDM-AP IDR reads which must all succeed and agree at one speed
+pub const SWD_SPEED_CHECK_READS: u32 = 32;
+
+/// `SwdSpeed` - SWD clock selection, `None` in `open_probe_with_speed` keeps the probe default
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SwdSpeed {
+    Fixed(u32),
+    /// fastest of `SWD_SPEEDS_KHZ` passing `verify_link`
+    Auto,
+}
+
+impl SwdSpeed {
+    /// `from_arg` - `auto` or clock in kHz
+    pub fn from_arg(value: &str) -> Option<Self> {
+        if value == "auto" {
+            return Some(SwdSpeed::Auto);
+        }
+        value.parse().ok().filter(|&khz| khz > 0).map(SwdSpeed::Fixed)
+    }
+}
+
+/// `verify_link` - scan APs and read MDM-AP IDR `reads` times, every fault or differing value fails
+pub fn verify_link<T: Transport + ?Sized>(iface: &mut T, reads: u32) -> Result<u32, Error> {
+    dp_health_check(iface)?;
+    let ports = scan_aps(iface)?.ports()?;
+    let idr = iface.read_ap_register(ports.mdm_ap, MKE_MDM_IDR_REG)?;
+    for _ in 1..reads {
+        let found = iface.read_ap_register(ports.mdm_ap, MKE_MDM_IDR_REG)?;
+        if found != idr {
+            return Err(Error::IdrMismatch { expected: idr, found });
+        }
+    }
+    Ok(idr)
+}
+
+fn set_speed(probe: &mut Probe, khz: u32) -> Result<u32, Error> {
+    probe.set_speed(khz).map_err(|err| Error::Probe { operation: "set SWD clock", source: err.into() })
+}
+
+/// `negotiate_speed` - step down `SWD_SPEEDS_KHZ` until `verify_link` passes, return the speed in kHz
+///
+/// The probe is opened and attached without reset for each step, the target keeps running.
+/// Probe open and clock set failures also step down. Target `voltage` faults end the negotiation,
+/// a slower clock would not help.
+pub fn negotiate_speed(selector: &ProbeSelector, voltage: Option<&VoltageRange>) -> Result<u32, Error> {
+    let mut last_error = None;
+    for khz in SWD_SPEEDS_KHZ {
+        let result = open_probe(selector).and_then(|mut probe| {
+            let actual = set_speed(&mut probe, khz)?;
+            let mut transport = open_transport(probe, ConnectStrategy::HotAttach, voltage)?;
+            Ok((actual, verify_link(&mut transport, SWD_SPEED_CHECK_READS)?))
+        });
+        match result {
+            Ok((actual, idr)) => {
+                log::info!("SWD clock {} kHz: {} MDM-AP IDR reads OK ({:#010X})", actual, SWD_SPEED_CHECK_READS, idr);
+                return Ok(actual);
+            }
+            Err(err @ (Error::TargetUnpowered { .. } | Error::TargetVoltageOutOfRange { .. })) => return Err(err),
+            Err(err) => {
+                log::warn!("SWD clock {} kHz failed, stepping down: {}", khz, report(&err));
+                last_error = Some(err);
+            }
+        }
+    }
+    Err(Error::SwdSpeedNegotiationFailed {
+        slowest: SWD_SPEEDS_KHZ[SWD_SPEEDS_KHZ.len() - 1],
+        source: last_error.map_or_else(|| "no speed tried".into(), |err| Box::new(err) as BoxError),
+    })
+}
+
+/// `open_probe_with_speed` - `open_probe` and set the SWD clock, negotiated first if `SwdSpeed::Auto`,
+/// `voltage` is checked by each negotiation attach
+pub fn open_probe_with_speed(
+    selector: &ProbeSelector,
+    speed: Option<SwdSpeed>,
+    voltage: Option<&VoltageRange>,
+) -> Result<Probe, Error> {
+    let khz = match speed {
+        None => return open_probe(selector),
+        Some(SwdSpeed::Fixed(khz)) => khz,
+        Some(SwdSpeed::Auto) => negotiate_speed(selector, voltage)?,
+    };
+    let mut probe = open_probe(selector)?;
+    let actual = set_speed(&mut probe, khz)?;
+    log::info!("SWD clock {} kHz", actual);
+    Ok(probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed_arg() {
+        assert_eq!(SwdSpeed::from_arg("auto"), Some(SwdSpeed::Auto));
+        assert_eq!(SwdSpeed::from_arg("1800"), Some(SwdSpeed::Fixed(1800)));
+        assert_eq!(SwdSpeed::from_arg("0"), None);
+        assert_eq!(SwdSpeed::from_arg("-5"), None);
+        assert_eq!(SwdSpeed::from_arg("Auto"), None);
+    }
+
+    #[test]
+    fn link_verified_by_idr_reads() {
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        assert_eq!(verify_link(&mut sim, SWD_SPEED_CHECK_READS).unwrap(), IDR_REG_CHECK_VALUE);
+    }
+
+    #[test]
+    fn faulting_link_fails() {
+        let mut sim = SimTarget::new(SimScenario::Normal);
+        sim.ap_faults = 1;
+        assert!(verify_link(&mut sim, SWD_SPEED_CHECK_READS).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use super::*;
+
+use std::fmt;
+
+use probe_rs::Probe;
+
+/// `KE1X_VOLTAGE_RANGE` - KE1x / KE0x / S32K1xx operating supply range
+pub const KE1X_VOLTAGE_RANGE: VoltageRange = VoltageRange { min_mv: 2700, max_mv: 5500 };
+/// `KL2X_VOLTAGE_RANGE` - KL2x operating supply range
+pub const KL2X_VOLTAGE_RANGE: VoltageRange = VoltageRange { min_mv: 1710, max_mv: 3600 };
+/// `TARGET_UNPOWERED_MV` - below this the target is taken as not powered at all
+pub const TARGET_UNPOWERED_MV: u32 = 500;
+
+/// `VoltageRange` - allowed target reference voltage, in mV
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VoltageRange {
+    pub min_mv: u32,
+    pub max_mv: u32,
+}
+
+impl VoltageRange {
+    /// `from_arg` - `MIN-MAX` in volts, e.g. `1.71-3.6`
+    pub fn from_arg(value: &str) -> Option<Self> {
+        let (min, max) = value.split_once('-')?;
+        let min: f32 = min.trim().parse().ok()?;
+        let max: f32 = max.trim().parse().ok()?;
+        if !(0.0..=max).contains(&min) {
+            return None;
+        }
+        Some(Self {
+            min_mv: (min * 1000.0).round() as u32,
+            max_mv: (max * 1000.0).round() as u32,
+        })
+    }
+
+    pub fn contains(&self, millivolts: u32) -> bool {
+        (self.min_mv..=self.max_mv).contains(&millivolts)
+    }
+
+    /// `union` - smallest range covering `self` and `other`
+    pub fn union(&self, other: &VoltageRange) -> Self {
+        Self {
+            min_mv: self.min_mv.min(other.min_mv),
+            max_mv: self.max_mv.max(other.max_mv),
+        }
+    }
+}
+
+impl fmt::Display for VoltageRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.2}-{:.2} V", self.min_mv as f32 / 1000.0, self.max_mv as f32 / 1000.0)
+    }
+}
+
+/// `check_target_voltage` - read target reference voltage, call before attach
+///
+/// `Error::TargetUnpowered` / `Error::TargetVoltageOutOfRange` if it is not in `range`.
+/// Probes without voltage sense pass with a warning, `Ok(None)`.
+pub fn check_target_voltage(probe: &mut Probe, range: &VoltageRange) -> Result<Option<u32>, Error> {
+    let voltage = match probe.get_target_voltage() {
+        Ok(Some(voltage)) => voltage,
+        Ok(None) => {
+            log::warn!("probe can't measure target voltage, not checked");
+            return Ok(None);
+        }
+        Err(err) => {
+            log::warn!("target voltage read failed, not checked: {}", err);
+            return Ok(None);
+        }
+    };
+    let millivolts = (voltage * 1000.0).round().max(0.0) as u32;
+    if millivolts < TARGET_UNPOWERED_MV {
+        return Err(Error::TargetUnpowered { millivolts });
+    }
+    if !range.contains(millivolts) {
+        return Err(Error::TargetVoltageOutOfRange {
+            millivolts,
+            min_mv: range.min_mv,
+            max_mv: range.max_mv,
+        });
+    }
+    log::info!("target voltage {:.2} V", voltage);
+    Ok(Some(millivolts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_arg_in_volts() {
+        assert_eq!(VoltageRange::from_arg("1.71-3.6"), Some(KL2X_VOLTAGE_RANGE));
+        assert_eq!(VoltageRange::from_arg(" 2.7 - 5.5 "), Some(KE1X_VOLTAGE_RANGE));
+        assert_eq!(VoltageRange::from_arg("0-3.3").map(|range| range.min_mv), Some(0));
+    }
+
+    #[test]
+    fn range_arg_rejected() {
+        assert!(VoltageRange::from_arg("3.6-1.71").is_none());
+        assert!(VoltageRange::from_arg("-1-3").is_none());
+        assert!(VoltageRange::from_arg("3.3").is_none());
+        assert!(VoltageRange::from_arg("low-high").is_none());
+    }
+
+    #[test]
+    fn default_range_by_family() {
+        assert_eq!(supply_range(Some("KE1xZ")).unwrap(), KE1X_VOLTAGE_RANGE);
+        assert_eq!(supply_range(Some("kl2x")).unwrap(), KL2X_VOLTAGE_RANGE);
+        assert!(matches!(supply_range(Some("KV31")), Err(Error::UnknownDevice { .. })));
+    }
+
+    #[test]
+    fn unknown_family_takes_union() {
+        let any = supply_range(None).unwrap();
+        assert_eq!(any, VoltageRange { min_mv: 1710, max_mv: 5500 });
+        /* a 1.8 V KL2x board passes the check before attach */
+        assert!(ConnectOptions::default().target_voltage.unwrap().contains(1800));
+        assert!(!KE1X_VOLTAGE_RANGE.contains(1800));
+    }
+}
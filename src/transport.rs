@@ -0,0 +1,327 @@
+use super::*;
+
+/// `Transport` - raw debug port access used by `MdmAP` and the connect sequence
+///
+/// Owned by this crate and free of probe-rs types, so the sequence can run over
+/// probe-rs (`ProbeRsTransport`), the software model (`SimTarget`), mocks or recorders.
+/// Access ports are addressed by index on the default DP.
+pub trait Transport {
+    /// `read_dp_register` - read raw register `address` of the debug port
+    fn read_dp_register(&mut self, address: u8) -> Result<u32, Error>;
+
+    /// `write_dp_register` - write raw register `address` of the debug port
+    fn write_dp_register(&mut self, address: u8, value: u32) -> Result<(), Error>;
+
+    /// `read_ap_register` - read raw register `address` of access port `ap`
+    fn read_ap_register(&mut self, ap: u8, address: u8) -> Result<u32, Error>;
+
+    /// `write_ap_register` - write raw register `address` of access port `ap`
+    fn write_ap_register(&mut self, ap: u8, address: u8, value: u32) -> Result<(), Error>;
+
+    /// `read_mem_word` - read 32-bit word over memory access port `ap`
+    fn read_mem_word(&mut self, ap: u8, address: u64) -> Result<u32, Error>;
+
+    /// `write_mem_word` - write 32-bit word over memory access port `ap`
+    fn write_mem_word(&mut self, ap: u8, address: u64, value: u32) -> Result<(), Error>;
+
+    /// `write_ap_register_no_retry` - `write_ap_register` done once, for writes with side effects
+    fn write_ap_register_no_retry(&mut self, ap: u8, address: u8, value: u32) -> Result<(), Error> {
+        self.write_ap_register(ap, address, value)
+    }
+
+    /// `write_mem_word_no_retry` - `write_mem_word` done once, for writes with side effects
+    fn write_mem_word_no_retry(&mut self, ap: u8, address: u64, value: u32) -> Result<(), Error> {
+        self.write_mem_word(ap, address, value)
+    }
+
+    /// `flush` - complete all queued writes
+    fn flush(&mut self) -> Result<(), Error>;
+
+    /// `set_nrst` - drive target NRST line, `true` - reset asserted (low)
+    fn set_nrst(&mut self, asserted: bool) -> Result<(), Error> {
+        Err(Error::Unsupported("NRST line"))
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+    fn read_dp_register(&mut self, address: u8) -> Result<u32, Error> {
+        (**self).read_dp_register(address)
+    }
+
+    fn write_dp_register(&mut self, address: u8, value: u32) -> Result<(), Error> {
+        (**self).write_dp_register(address, value)
+    }
+
+    fn read_ap_register(&mut self, ap: u8, address: u8) -> Result<u32, Error> {
+        (**self).read_ap_register(ap, address)
+    }
+
+    fn write_ap_register(&mut self, ap: u8, address: u8, value: u32) -> Result<(), Error> {
+        (**self).write_ap_register(ap, address, value)
+    }
+
+    fn read_mem_word(&mut self, ap: u8, address: u64) -> Result<u32, Error> {
+        (**self).read_mem_word(ap, address)
+    }
+
+    fn write_mem_word(&mut self, ap: u8, address: u64, value: u32) -> Result<(), Error> {
+        (**self).write_mem_word(ap, address, value)
+    }
+
+    fn write_ap_register_no_retry(&mut self, ap: u8, address: u8, value: u32) -> Result<(), Error> {
+        (**self).write_ap_register_no_retry(ap, address, value)
+    }
+
+    fn write_mem_word_no_retry(&mut self, ap: u8, address: u64, value: u32) -> Result<(), Error> {
+        (**self).write_mem_word_no_retry(ap, address, value)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        (**self).flush()
+    }
+
+    fn set_nrst(&mut self, asserted: bool) -> Result<(), Error> {
+        (**self).set_nrst(asserted)
+    }
+}
+
+/// `ProbeRsTransport` - `Transport` adapter for probe-rs `ArmProbeInterface`
+pub struct ProbeRsTransport<'probe> {
+    /// `None` only if `set_nrst` could not open the ARM interface again
+    iface: Option<Box<dyn ArmProbeInterface + 'probe>>,
+}
+
+impl<'probe> ProbeRsTransport<'probe> {
+    pub fn new(iface: Box<dyn ArmProbeInterface + 'probe>) -> Self {
+        Self { iface: Some(iface) }
+    }
+
+    /// `interface` - underlying probe-rs interface, for access not covered by `Transport`
+    pub fn interface(&mut self) -> Result<&mut (dyn ArmProbeInterface + 'probe), Error> {
+        match self.iface.as_mut() {
+            Some(iface) => Ok(iface.as_mut()),
+            None => Err(Error::Probe {
+                operation: "ARM interface access",
+                source: "ARM interface was not reopened after NRST change".into(),
+            }),
+        }
+    }
+
+    pub fn into_inner(self) -> Option<Box<dyn ArmProbeInterface + 'probe>> {
+        self.iface
+    }
+
+    fn ap_address(ap: u8) -> ApAddress {
+        ApAddress {
+            dp: DpAddress::Default,
+            ap,
+        }
+    }
+}
+
+impl<'probe> Transport for ProbeRsTransport<'probe> {
+    fn read_dp_register(&mut self, address: u8) -> Result<u32, Error> {
+        self.interface()?
+            .read_raw_dp_register(DpAddress::Default, address)
+            .map_err(|err| Error::DpRead {
+                address,
+                source: err.into(),
+            })
+    }
+
+    fn write_dp_register(&mut self, address: u8, value: u32) -> Result<(), Error> {
+        self.interface()?
+            .write_raw_dp_register(DpAddress::Default, address, value)
+            .map_err(|err| Error::DpWrite {
+                address,
+                value,
+                source: err.into(),
+            })
+    }
+
+    fn read_ap_register(&mut self, ap: u8, address: u8) -> Result<u32, Error> {
+        self.interface()?
+            .read_raw_ap_register(Self::ap_address(ap), address)
+            .map_err(|err| Error::ApRead {
+                ap,
+                address,
+                source: err.into(),
+            })
+    }
+
+    fn write_ap_register(&mut self, ap: u8, address: u8, value: u32) -> Result<(), Error> {
+        self.interface()?
+            .write_raw_ap_register(Self::ap_address(ap), address, value)
+            .map_err(|err| Error::ApWrite {
+                ap,
+                address,
+                value,
+                source: err.into(),
+            })
+    }
+
+    fn read_mem_word(&mut self, ap: u8, address: u64) -> Result<u32, Error> {
+        let mut memory = self
+            .interface()?
+            .memory_interface(MemoryAp::new(Self::ap_address(ap)))
+            .map_err(|err| Error::MemRead {
+                ap,
+                address,
+                source: err.into(),
+            })?;
+        memory.read_word_32(address).map_err(|err| Error::MemRead {
+            ap,
+            address,
+            source: err.into(),
+        })
+    }
+
+    fn write_mem_word(&mut self, ap: u8, address: u64, value: u32) -> Result<(), Error> {
+        let mut memory = self
+            .interface()?
+            .memory_interface(MemoryAp::new(Self::ap_address(ap)))
+            .map_err(|err| Error::MemWrite {
+                ap,
+                address,
+                value,
+                source: err.into(),
+            })?;
+        memory
+            .write_word_32(address, value)
+            .map_err(|err| Error::MemWrite {
+                ap,
+                address,
+                value,
+                source: err.into(),
+            })?;
+        /* memory interface is dropped here, don't leave the write queued in it */
+        memory.flush().map_err(|err| Error::MemWrite {
+            ap,
+            address,
+            value,
+            source: err.into(),
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.interface()?
+            .flush()
+            .map_err(|err| Error::Probe {
+                operation: "flush",
+                source: err.into(),
+            })
+    }
+
+    /// NRST goes through the probe (`target_reset_assert` / `target_reset_deassert`), not `swj_pins`
+    /// which ST-Link does not support: the ARM interface is closed, NRST driven and the interface
+    /// opened again. Debug state of the core (C_HALT, DEMCR) survives the re-open.
+    fn set_nrst(&mut self, asserted: bool) -> Result<(), Error> {
+        let iface = self.iface.take().ok_or(Error::Unsupported("NRST without ARM interface"))?;
+        let mut probe = iface.close();
+        let driven = if asserted {
+            probe.target_reset_assert().map_err(|err| Error::Probe { operation: "assert NRST", source: err.into() })
+        } else {
+            probe.target_reset_deassert().map_err(|err| Error::Probe { operation: "release NRST", source: err.into() })
+        };
+        let iface = probe
+            .try_into_arm_interface()
+            .map_err(|(_, err)| Error::Probe { operation: "reopen ARM interface", source: err.into() })?
+            .initialize_unspecified()
+            .map_err(|(_, err)| Error::Probe { operation: "init ARM interface", source: err.into() })?;
+        self.iface = Some(iface);
+        driven
+    }
+}
+
+/// `AP_ACCESS_RETRIES` - default retries of a failed AP / memory access by `RetryTransport`
+pub const AP_ACCESS_RETRIES: u32 = 2;
+
+/// `RetryTransport` - retry failed AP and memory accesses after clearing DP sticky errors
+///
+/// A FAULT or overrun sets a sticky flag in DP CTRL/STAT and every following AP access
+/// fails until it is cleared, so one glitch on the wires would fail the whole sequence.
+/// Only reads and idempotent register writes are retried, a write which may have been done
+/// before the fault was seen must be safe to repeat. `write_*_no_retry`, DP accesses, `flush`
+/// and `set_nrst` are passed through.
+pub struct RetryTransport<T: Transport> {
+    inner: T,
+    retries: u32,
+}
+
+impl<T: Transport> RetryTransport<T> {
+    pub fn new(inner: T, retries: u32) -> Self {
+        Self { inner, retries }
+    }
+
+    pub fn inner(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn retry<R, F>(&mut self, access: F) -> Result<R, Error>
+    where
+        F: Fn(&mut T) -> Result<R, Error>,
+    {
+        let mut attempt = 0;
+        loop {
+            match access(&mut self.inner) {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt < self.retries => {
+                    attempt += 1;
+                    log::warn!("{}, retry {} of {}", report(&err), attempt, self.retries);
+                    match clear_sticky_errors(&mut self.inner) {
+                        Ok(ctrl_stat) if ctrl_stat.has_sticky_errors() => log::warn!("cleared {}", ctrl_stat),
+                        Ok(_) => {}
+                        Err(err) => log::warn!("sticky error clear failed: {}", report(&err)),
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl<T: Transport> Transport for RetryTransport<T> {
+    fn read_dp_register(&mut self, address: u8) -> Result<u32, Error> {
+        self.inner.read_dp_register(address)
+    }
+
+    fn write_dp_register(&mut self, address: u8, value: u32) -> Result<(), Error> {
+        self.inner.write_dp_register(address, value)
+    }
+
+    fn read_ap_register(&mut self, ap: u8, address: u8) -> Result<u32, Error> {
+        self.retry(|inner| inner.read_ap_register(ap, address))
+    }
+
+    fn write_ap_register(&mut self, ap: u8, address: u8, value: u32) -> Result<(), Error> {
+        self.retry(|inner| inner.write_ap_register(ap, address, value))
+    }
+
+    fn read_mem_word(&mut self, ap: u8, address: u64) -> Result<u32, Error> {
+        self.retry(|inner| inner.read_mem_word(ap, address))
+    }
+
+    fn write_mem_word(&mut self, ap: u8, address: u64, value: u32) -> Result<(), Error> {
+        self.retry(|inner| inner.write_mem_word(ap, address, value))
+    }
+
+    fn write_ap_register_no_retry(&mut self, ap: u8, address: u8, value: u32) -> Result<(), Error> {
+        self.inner.write_ap_register_no_retry(ap, address, value)
+    }
+
+    fn write_mem_word_no_retry(&mut self, ap: u8, address: u64, value: u32) -> Result<(), Error> {
+        self.inner.write_mem_word_no_retry(ap, address, value)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+
+    fn set_nrst(&mut self, asserted: bool) -> Result<(), Error> {
+        self.inner.set_nrst(asserted)
+    }
+}
@@ -0,0 +1,271 @@
+use super::*;
+
+use std::fmt;
+use std::io::Write;
+
+/// `DHCSR_FIELDS` - ARMv6-M DHCSR bits reported by watch mode
+const DHCSR_FIELDS: [(&str, u32); 10] = [
+    ("C_DEBUGEN", 1 << 0),
+    ("C_HALT", 1 << 1),
+    ("C_STEP", 1 << 2),
+    ("C_MASKINTS", 1 << 3),
+    ("S_REGRDY", 1 << 16),
+    ("S_HALT", 1 << 17),
+    ("S_SLEEP", 1 << 18),
+    ("S_LOCKUP", 1 << 19),
+    ("S_RETIRE_ST", 1 << 24),
+    ("S_RESET_ST", 1 << 25),
+];
+
+/// `WATCH_INTERVAL_MS` - default poll period of watch mode
+pub const WATCH_INTERVAL_MS: u64 = 10;
+
+/// `WatchConfig` - what and how often `watch` polls
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WatchConfig {
+    /// poll period, polls are scheduled from start so USB latency does not drift the rate,
+    /// must not be zero
+    pub interval: time::Duration,
+    /// also poll core DHCSR over the AHB MEM-AP
+    pub dhcsr: bool,
+    /// stop after this time, `None` - watch until the process is stopped
+    pub duration: Option<time::Duration>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            interval: time::Duration::from_millis(WATCH_INTERVAL_MS),
+            dhcsr: false,
+            duration: None,
+        }
+    }
+}
+
+/// `WatchChange` - one observed transition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchChange {
+    /// bit `field` of `register` changed
+    Bit {
+        register: &'static str,
+        field: &'static str,
+        old: bool,
+        new: bool,
+    },
+    /// `register` became readable / unreadable (DHCSR is blocked when secured or in low power)
+    Access { register: &'static str, readable: bool },
+}
+
+/// `WatchEvent` - change with time since watch start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchEvent {
+    pub timestamp: time::Duration,
+    pub change: WatchChange,
+}
+
+impl fmt::Display for WatchEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:>12.6} s] ", self.timestamp.as_secs_f64())?;
+        match &self.change {
+            WatchChange::Bit {
+                register,
+                field,
+                old,
+                new,
+            } => write!(f, "{}.{} {} -> {}", register, field, *old as u8, *new as u8),
+            WatchChange::Access { register, readable } => {
+                let state = if *readable { "readable" } else { "not readable" };
+                write!(f, "{} {}", register, state)
+            }
+        }
+    }
+}
+
+/// `WatchSnapshot` - one poll of watched registers
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct WatchSnapshot {
+    mdm_ap: MdmAP,
+    /// `None` - not watched or read failed
+    dhcsr: Option<u32>,
+}
+
+impl WatchSnapshot {
+    fn read<T: Transport + ?Sized>(
+        iface: &mut T,
+        config: &WatchConfig,
+        ports: &ApPorts,
+    ) -> Result<Self, Error> {
+        let mdm_ap = MdmAP::read_mdm_ap_register_at(iface, ports.mdm_ap, false)?;
+        let dhcsr = if config.dhcsr {
+            iface
+                .read_mem_word(ports.mem_ap, Dhcsr::get_mmio_address())
+                .ok()
+        } else {
+            None
+        };
+        Ok(Self { mdm_ap, dhcsr })
+    }
+
+    fn changes(&self, new: &WatchSnapshot, timestamp: time::Duration) -> Vec<WatchEvent> {
+        let mut changes: Vec<WatchChange> = self
+            .mdm_ap
+            .compare(&new.mdm_ap)
+            .changes
+            .into_iter()
+            .map(|change| WatchChange::Bit {
+                register: change.register,
+                field: change.field,
+                old: change.old,
+                new: change.new,
+            })
+            .collect();
+        match (self.dhcsr, new.dhcsr) {
+            (Some(old), Some(new)) => {
+                for (field, mask) in DHCSR_FIELDS {
+                    if (old ^ new) & mask != 0 {
+                        changes.push(WatchChange::Bit {
+                            register: "DHCSR",
+                            field,
+                            old: old & mask != 0,
+                            new: new & mask != 0,
+                        });
+                    }
+                }
+            }
+            (None, Some(_)) => changes.push(WatchChange::Access {
+                register: "DHCSR",
+                readable: true,
+            }),
+            (Some(_), None) => changes.push(WatchChange::Access {
+                register: "DHCSR",
+                readable: false,
+            }),
+            (None, None) => {}
+        }
+        changes
+            .into_iter()
+            .map(|change| WatchEvent { timestamp, change })
+            .collect()
+    }
+}
+
+/// `watch` - poll MDM-AP status/control (and DHCSR) and write an event on every bit change
+///
+/// First line is the initial state, then one line per `WatchEvent`. Output is flushed after
+/// every event, so a file can be followed while watching. Return number of events written.
+pub fn watch<T, W>(iface: &mut T, config: &WatchConfig, out: &mut W) -> Result<u64, Error>
+where
+    T: Transport + ?Sized,
+    W: Write,
+{
+    let start = time::Instant::now();
+    let started_at = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let ports = scan_aps(iface)?.ports()?;
+    let mut last = WatchSnapshot::read(iface, config, &ports)?;
+    write!(
+        out,
+        "[{:>12.6} s] watch started at unix {:.3}, MDM_STATUS {:#010X}, MDM_CONTROL {:#010X}",
+        0.0,
+        started_at.as_secs_f64(),
+        last.mdm_ap.status.value(),
+        last.mdm_ap.control.to_u32()
+    )?;
+    match (config.dhcsr, last.dhcsr) {
+        (true, Some(dhcsr)) => writeln!(out, ", DHCSR {:#010X}", dhcsr)?,
+        (true, None) => writeln!(out, ", DHCSR not readable")?,
+        (false, _) => writeln!(out)?,
+    }
+    out.flush()?;
+
+    let mut events: u64 = 0;
+    let deadline = config.duration.map(|duration| start + duration);
+    let mut next_poll = start;
+    loop {
+        /* polls missed by a slow probe are skipped, not caught up */
+        next_poll = (next_poll + config.interval).max(time::Instant::now());
+        if let Some(deadline) = deadline {
+            if next_poll > deadline {
+                break;
+            }
+        }
+        thread::sleep(next_poll.saturating_duration_since(time::Instant::now()));
+
+        let snapshot = WatchSnapshot::read(iface, config, &ports)?;
+        for event in last.changes(&snapshot, start.elapsed()) {
+            writeln!(out, "{}", event)?;
+            events += 1;
+        }
+        out.flush()?;
+        last = snapshot;
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `SecureAfter` - `SimTarget` whose System Security gets set at access `at`
+    struct SecureAfter {
+        sim: SimTarget,
+        at: u64,
+    }
+
+    impl SecureAfter {
+        fn tick(&mut self) {
+            if self.sim.accesses() >= self.at {
+                self.sim.secured = true;
+            }
+        }
+    }
+
+    impl Transport for SecureAfter {
+        fn read_dp_register(&mut self, address: u8) -> Result<u32, Error> {
+            self.sim.read_dp_register(address)
+        }
+
+        fn write_dp_register(&mut self, address: u8, value: u32) -> Result<(), Error> {
+            self.sim.write_dp_register(address, value)
+        }
+
+        fn read_ap_register(&mut self, ap: u8, address: u8) -> Result<u32, Error> {
+            self.tick();
+            self.sim.read_ap_register(ap, address)
+        }
+
+        fn write_ap_register(&mut self, ap: u8, address: u8, value: u32) -> Result<(), Error> {
+            self.sim.write_ap_register(ap, address, value)
+        }
+
+        fn read_mem_word(&mut self, ap: u8, address: u64) -> Result<u32, Error> {
+            self.sim.read_mem_word(ap, address)
+        }
+
+        fn write_mem_word(&mut self, ap: u8, address: u64, value: u32) -> Result<(), Error> {
+            self.sim.write_mem_word(ap, address, value)
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            self.sim.flush()
+        }
+    }
+
+    #[test]
+    fn bit_flip_is_one_event() {
+        let mut target = SecureAfter { sim: SimTarget::new(SimScenario::Normal), at: 20 };
+        let config = WatchConfig {
+            interval: time::Duration::from_millis(1),
+            dhcsr: false,
+            duration: Some(time::Duration::from_millis(50)),
+        };
+        let mut out = Vec::new();
+        let events = watch(&mut target, &config, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(events, 1, "{}", out);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", out);
+        assert!(lines[1].ends_with("MDM_STATUS.security 0 -> 1"), "{}", lines[1]);
+    }
+}